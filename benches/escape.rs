@@ -1,10 +1,13 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
 use std::{fs, hint::black_box};
 
-use criterion::{Criterion, criterion_group, criterion_main};
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
 
 #[cfg(not(feature = "codspeed"))]
 use generic::escape_generic;
-use json_escape_simd::escape;
+use json_escape_simd::{escape, escape_exact, with_escaped};
 
 #[cfg(not(feature = "codspeed"))]
 mod generic;
@@ -54,6 +57,13 @@ fn run_benchmarks(c: &mut Criterion, sources: &[String], prefix: &str) {
             }
         })
     });
+    c.bench_function(&format!("{} with_escaped simd", prefix), |b| {
+        b.iter(|| {
+            for source in sources {
+                with_escaped(source, |escaped| black_box(escaped.len()));
+            }
+        })
+    });
     #[cfg(not(feature = "codspeed"))]
     c.bench_function(&format!("{} escape sonic", prefix), |b| {
         b.iter(|| {
@@ -106,6 +116,68 @@ fn short_string_benchmark(c: &mut Criterion) {
     run_benchmarks(c, &sources, "short string");
 }
 
+fn dense_escape_benchmark(c: &mut Criterion) {
+    // Quotes, backslashes and newlines every few bytes, where the kernels spend
+    // most of their time escaping rather than scanning.
+    let sources = vec![
+        "\"\\\"\\\"\\\"\\".repeat(1000),
+        r#"{"id":1,"name":"foo","tags":["a","b"],"path":"C:\\Users\\foo"}"#.repeat(200),
+        "console.log(\"a\", 'b', \"c\\n\");\n".repeat(500),
+    ];
+    run_benchmarks(c, &sources, "dense escapes");
+}
+
+fn json_in_json_benchmark(c: &mut Criterion) {
+    // Serialized event payloads stored as strings, as in log pipelines: a quote
+    // every few bytes, with the odd nested escape.
+    let sources = (0..200)
+        .map(|i| {
+            serde_json::json!({
+                "id": i,
+                "type": "page_view",
+                "user": { "name": format!("user {i}"), "agent": "Mozilla/5.0 (X11; Linux)" },
+                "url": format!("https://example.com/items/{i}?ref=\"home\""),
+                "tags": ["a", "b", "c"],
+                "message": "line one\nline two\t\"quoted\"",
+            })
+            .to_string()
+        })
+        .collect::<Vec<_>>();
+    run_benchmarks(c, &sources, "json in json");
+}
+
+fn sve_benchmark(c: &mut Criterion) {
+    // The dispatcher only picks SVE over NEON for vectors wider than 128 bits;
+    // this compares the two on the same CPU, on clean and on dense input.
+    #[cfg(target_arch = "aarch64")]
+    {
+        use json_escape_simd::__aarch64_kernels::escape_with;
+        let corpora = [
+            ("fixtures", get_affine_sources()),
+            (
+                "dense escapes",
+                vec![r#"{"id":1,"tags":["a","b"]}"#.repeat(400)],
+            ),
+        ];
+        for (prefix, sources) in corpora {
+            for kernel in ["neon", "sve", "sve2"] {
+                if sources.is_empty() || escape_with(kernel, &sources[0]).is_none() {
+                    continue;
+                }
+                c.bench_function(&format!("{prefix} kernel {kernel}"), |b| {
+                    b.iter(|| {
+                        for source in &sources {
+                            black_box(escape_with(kernel, source));
+                        }
+                    })
+                });
+            }
+        }
+    }
+    #[cfg(not(target_arch = "aarch64"))]
+    let _ = c;
+}
+
 fn rxjs_benchmark(c: &mut Criterion) {
     let sources = get_rxjs_sources();
     if !sources.is_empty() {
@@ -120,10 +192,159 @@ fn affine_sources_benchmark(c: &mut Criterion) {
     }
 }
 
+fn exact_alloc_benchmark(c: &mut Criterion) {
+    let sources = get_affine_sources();
+    if sources.is_empty() {
+        return;
+    }
+    c.bench_function("fixtures exact alloc escape", |b| {
+        b.iter(|| {
+            for source in &sources {
+                black_box(escape(source));
+            }
+        })
+    });
+    c.bench_function("fixtures exact alloc escape_exact", |b| {
+        b.iter(|| {
+            for source in &sources {
+                black_box(escape_exact(source));
+            }
+        })
+    });
+}
+
+/// Heap bytes requested per iteration, the memory side of the
+/// `escape`/`escape_exact` trade-off.
+fn allocated_bytes_benchmark(c: &mut Criterion<AllocatedBytes>) {
+    let sources = get_affine_sources();
+    if sources.is_empty() {
+        return;
+    }
+    for (name, escape) in [
+        ("escape", escape as fn(&str) -> String),
+        ("escape_exact", escape_exact),
+    ] {
+        c.bench_function(&format!("fixtures allocated bytes {name}"), |b| {
+            b.iter(|| {
+                for source in &sources {
+                    black_box(escape(source));
+                }
+            })
+        });
+    }
+}
+
+/// Counts the bytes this thread asks the heap for, for [`AllocatedBytes`].
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count(bytes: usize) {
+    let _ = ALLOCATED.try_with(|n| n.set(n.get() + bytes));
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// Measures heap bytes allocated instead of time.
+struct AllocatedBytes;
+
+impl Measurement for AllocatedBytes {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        ALLOCATED.get()
+    }
+
+    fn end(&self, start: usize) -> usize {
+        ALLOCATED.get() - start
+    }
+
+    fn add(&self, v1: &usize, v2: &usize) -> usize {
+        v1 + v2
+    }
+
+    fn zero(&self) -> usize {
+        0
+    }
+
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        self
+    }
+}
+
+impl ValueFormatter for AllocatedBytes {
+    fn scale_values(&self, typical: f64, values: &mut [f64]) -> &'static str {
+        let (denominator, unit) = if typical < 1024.0 {
+            (1.0, "B")
+        } else if typical < 1024.0 * 1024.0 {
+            (1024.0, "KiB")
+        } else {
+            (1024.0 * 1024.0, "MiB")
+        };
+        for value in values {
+            *value /= denominator;
+        }
+        unit
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        let (input, unit) = match *throughput {
+            Throughput::Bytes(n) | Throughput::BytesDecimal(n) => (n, "B/input byte"),
+            Throughput::Elements(n) => (n, "B/element"),
+        };
+        for value in values {
+            *value /= input as f64;
+        }
+        unit
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+}
+
 criterion_group!(
     benches,
     short_string_benchmark,
+    dense_escape_benchmark,
+    json_in_json_benchmark,
+    sve_benchmark,
     rxjs_benchmark,
-    affine_sources_benchmark
+    affine_sources_benchmark,
+    exact_alloc_benchmark
+);
+criterion_group!(
+    name = alloc_benches;
+    config = Criterion::default().with_measurement(AllocatedBytes);
+    targets = allocated_bytes_benchmark
 );
-criterion_main!(benches);
+criterion_main!(benches, alloc_benches);
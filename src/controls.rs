@@ -0,0 +1,178 @@
+//! What to do with NUL, or all C0 control characters, instead of escaping
+//! them as `\u00XX`.
+//!
+//! PostgreSQL's `jsonb` rejects `\u0000`, and C consumers may stop at it. The
+//! policy is applied where the kernels' slow path escapes the controls, so the
+//! input is still escaped in one pass, and inputs without controls never get
+//! there.
+
+use std::cell::Cell;
+use std::fmt;
+
+use crate::simd::{Quote, SlowPath};
+use crate::{escape_into, escape_to_with, grow};
+
+/// Which control characters a [`ControlPolicy`] applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlChars {
+    /// Only NUL (U+0000).
+    Nul,
+    /// All of U+0000 to U+001F.
+    C0,
+}
+
+/// What a [`ControlPolicy`] does with the control characters it applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlAction {
+    /// Escape them, as [`escape`](crate::escape) does.
+    Escape,
+    /// Leave them out.
+    Drop,
+    /// Replace them with U+FFFD.
+    Replace,
+    /// Fail with a [`ControlCharError`].
+    Reject,
+}
+
+/// How [`escape_with_policy`] treats control characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlPolicy {
+    /// The control characters the policy applies to. The others are escaped.
+    pub chars: ControlChars,
+    /// What to do with them.
+    pub action: ControlAction,
+}
+
+impl ControlPolicy {
+    /// Applies `action` to NUL only.
+    pub const fn nul(action: ControlAction) -> Self {
+        ControlPolicy {
+            chars: ControlChars::Nul,
+            action,
+        }
+    }
+
+    /// Applies `action` to all C0 control characters.
+    pub const fn c0(action: ControlAction) -> Self {
+        ControlPolicy {
+            chars: ControlChars::C0,
+            action,
+        }
+    }
+
+    #[inline(always)]
+    fn applies_to(self, b: u8) -> bool {
+        match self.chars {
+            ControlChars::Nul => b == 0,
+            ControlChars::C0 => b < 0x20,
+        }
+    }
+}
+
+/// The kernels' slow path under a policy other than [`ControlAction::Escape`].
+#[derive(Clone, Copy)]
+struct Policed<'a> {
+    policy: ControlPolicy,
+    /// The start of the input, to tell the offset of a rejected control.
+    start: *const u8,
+    /// The offset of the first rejected control.
+    rejected: &'a Cell<Option<usize>>,
+}
+
+impl SlowPath for Policed<'_> {
+    #[inline(always)]
+    fn short_controls(self) -> bool {
+        // NUL has no short escape, so the dense paths hand it over anyway.
+        self.policy.chars == ControlChars::Nul
+    }
+
+    #[inline(always)]
+    unsafe fn escape_byte(self, src: *const u8, dst: &mut *mut u8) {
+        if !self.policy.applies_to(unsafe { *src }) {
+            return unsafe { Quote.escape_byte(src, dst) };
+        }
+        match self.policy.action {
+            ControlAction::Escape => unsafe { Quote.escape_byte(src, dst) },
+            ControlAction::Drop => {}
+            ControlAction::Replace => unsafe {
+                std::ptr::copy_nonoverlapping("\u{fffd}".as_ptr(), *dst, 3);
+                *dst = dst.add(3);
+            },
+            ControlAction::Reject => {
+                if self.rejected.get().is_none() {
+                    let offset = unsafe { src.offset_from(self.start) } as usize;
+                    self.rejected.set(Some(offset));
+                }
+            }
+        }
+    }
+}
+
+/// A control character rejected by [`ControlAction::Reject`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlCharError {
+    offset: usize,
+    byte: u8,
+}
+
+impl ControlCharError {
+    /// Byte offset of the control character in the input.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The control character.
+    pub fn byte(&self) -> u8 {
+        self.byte
+    }
+}
+
+impl fmt::Display for ControlCharError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "control character U+{:04X} at byte offset {}",
+            self.byte, self.offset
+        )
+    }
+}
+
+impl std::error::Error for ControlCharError {}
+
+/// Like [`escape`](crate::escape), but treats control characters according to
+/// `policy`.
+pub fn escape_with_policy(value: &str, policy: ControlPolicy) -> Result<String, ControlCharError> {
+    let mut dst = Vec::new();
+    escape_into_with_policy(value, policy, &mut dst)?;
+    // SAFETY: the escaped output is valid UTF-8.
+    Ok(unsafe { String::from_utf8_unchecked(dst) })
+}
+
+/// Like [`escape_into`], but treats control characters according to `policy`.
+/// On error, `dst` is left as it was.
+pub fn escape_into_with_policy(
+    value: &str,
+    policy: ControlPolicy,
+    dst: &mut Vec<u8>,
+) -> Result<(), ControlCharError> {
+    if policy.action == ControlAction::Escape {
+        escape_into(value, dst);
+        return Ok(());
+    }
+    let len = dst.len();
+    let rejected = Cell::new(None);
+    let slow = Policed {
+        policy,
+        start: value.as_ptr(),
+        rejected: &rejected,
+    };
+    let Ok(()) = escape_to_with(value, dst, grow, slow);
+    if let Some(offset) = rejected.get() {
+        dst.truncate(len);
+        return Err(ControlCharError {
+            offset,
+            byte: value.as_bytes()[offset],
+        });
+    }
+    Ok(())
+}
@@ -3,11 +3,43 @@
 //! Only takes the string escaping part to avoid the abstraction overhead.
 
 #![allow(clippy::incompatible_msrv)]
+#![cfg_attr(
+    all(target_arch = "loongarch64", feature = "nightly"),
+    feature(stdarch_loongarch)
+)]
+#![cfg_attr(
+    all(target_arch = "powerpc64", feature = "nightly"),
+    feature(
+        stdarch_powerpc,
+        stdarch_powerpc_feature_detection,
+        powerpc_target_feature
+    )
+)]
+#![cfg_attr(
+    all(target_arch = "s390x", feature = "nightly"),
+    feature(stdarch_s390x)
+)]
+#![cfg_attr(feature = "portable-simd", feature(portable_simd))]
 
-#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
-use std::arch::is_x86_feature_detected;
+use std::cell::Cell;
+use std::collections::TryReserveError;
+use std::convert::Infallible;
+use std::fmt;
+use std::mem::MaybeUninit;
 
+use simd::{Quote, SlowPath};
+
+mod controls;
+mod profile;
 mod simd;
+mod sink;
+
+pub use controls::{
+    ControlAction, ControlCharError, ControlChars, ControlPolicy, escape_into_with_policy,
+    escape_with_policy,
+};
+pub use profile::{Profile, escape_into_with_profile, escape_with_profile};
+pub use sink::EscapeSink;
 
 pub(crate) const QUOTE_TAB: [(u8, [u8; 8]); 256] = [
     // 0x00 ~ 0x1f
@@ -285,46 +317,226 @@ pub(crate) const NEED_ESCAPED: [u8; 256] = [
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
 ];
 
+/// Writes `value` escaped and quoted to `dst`, which must hold
+/// `scratch_len(value.len())` bytes, and returns how many bytes it wrote.
 #[inline(always)]
 fn format_string(value: &str, dst: &mut [u8]) -> usize {
+    format_string_with(value, dst, Quote)
+}
+
+/// `--cfg json_escape_simd_fallback` runs the scalar fallback kernel whatever
+/// the CPU supports, so that it can be tested natively and under sanitizers.
+#[cfg(json_escape_simd_fallback)]
+#[inline(always)]
+fn format_string_with<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
+    simd::fallback::format_string(value, dst, slow)
+}
+
+#[cfg(not(json_escape_simd_fallback))]
+#[inline(always)]
+fn format_string_with<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
     #[cfg(target_arch = "aarch64")]
     {
-        let has_neon = cfg!(target_os = "macos") || std::arch::is_aarch64_feature_detected!("neon");
-        if has_neon {
-            unsafe { simd::neon::format_string(value, dst) }
-        } else {
-            simd::v128::format_string(value, dst)
+        // NEON comes first: it expands dense escapes in-register, which the SVE
+        // kernel cannot, so SVE only wins with vectors wider than NEON's 128
+        // bits. Every SVE CPU has NEON, so that is checked up front. The
+        // detection macros fold to `true` when the feature is enabled at
+        // compile time.
+        if std::arch::is_aarch64_feature_detected!("sve")
+            && unsafe { simd::sve::vector_bytes() } > 16
+        {
+            if std::arch::is_aarch64_feature_detected!("sve2") {
+                return unsafe { simd::sve::format_string_sve2(value, dst, slow) };
+            }
+            return unsafe { simd::sve::format_string(value, dst, slow) };
+        }
+        // NEON is part of the baseline of every mainstream aarch64 target, so
+        // the kernel is usually selected at compile time.
+        #[cfg(target_feature = "neon")]
+        {
+            unsafe { simd::neon::format_string(value, dst, slow) }
+        }
+        #[cfg(not(target_feature = "neon"))]
+        {
+            let has_neon =
+                cfg!(target_os = "macos") || std::arch::is_aarch64_feature_detected!("neon");
+            if has_neon {
+                unsafe { simd::neon::format_string(value, dst, slow) }
+            } else {
+                simd::fallback::format_string(value, dst, slow)
+            }
         }
     }
 
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     {
-        #[cfg(feature = "avx512")]
+        // When the target statically guarantees a kernel's features (e.g.
+        // `-C target-cpu=native`), call it directly without runtime detection.
+        #[cfg(all(target_feature = "avx512bw", target_feature = "avx512vl"))]
+        {
+            unsafe { simd::avx512::format_string(value, dst, slow) }
+        }
+        #[cfg(all(
+            target_feature = "avx2",
+            not(all(target_feature = "avx512bw", target_feature = "avx512vl"))
+        ))]
+        {
+            #[cfg(feature = "avx512")]
+            {
+                if is_x86_feature_detected!("avx512bw") && is_x86_feature_detected!("avx512vl") {
+                    return unsafe { simd::avx512::format_string(value, dst, slow) };
+                }
+            }
+            unsafe { simd::avx2::format_string(value, dst, slow) }
+        }
+        #[cfg(not(target_feature = "avx2"))]
+        {
+            #[cfg(feature = "avx512")]
+            {
+                // The avx512 kernel uses AVX-512BW byte compares (vpcmpub) and
+                // masked loads/stores (VL), so require both at runtime, not just F.
+                if is_x86_feature_detected!("avx512bw") && is_x86_feature_detected!("avx512vl") {
+                    return unsafe { simd::avx512::format_string(value, dst, slow) };
+                }
+            }
+            if is_x86_feature_detected!("avx2") {
+                unsafe { simd::avx2::format_string(value, dst, slow) }
+            } else if is_x86_feature_detected!("ssse3") {
+                unsafe { simd::ssse3::format_string(value, dst, slow) }
+            } else if is_x86_feature_detected!("sse2") {
+                unsafe { simd::sse2::format_string(value, dst, slow) }
+            } else {
+                simd::fallback::format_string(value, dst, slow)
+            }
+        }
+    }
+
+    #[cfg(target_arch = "riscv64")]
+    {
+        #[cfg(target_feature = "v")]
+        {
+            unsafe { simd::rvv::format_string(value, dst, slow) }
+        }
+        #[cfg(not(target_feature = "v"))]
         {
-            // The avx512 kernel uses AVX-512BW byte compares (vpcmpub) and
-            // masked loads/stores (VL), so require both at runtime, not just F.
-            if is_x86_feature_detected!("avx512bw") && is_x86_feature_detected!("avx512vl") {
-                return unsafe { simd::avx512::format_string(value, dst) };
+            if simd::rvv::is_available() {
+                unsafe { simd::rvv::format_string(value, dst, slow) }
+            } else {
+                simd::fallback::format_string(value, dst, slow)
             }
         }
-        if is_x86_feature_detected!("avx2") {
-            unsafe { simd::avx2::format_string(value, dst) }
-        } else if is_x86_feature_detected!("sse2") {
-            unsafe { simd::sse2::format_string(value, dst) }
+    }
+
+    #[cfg(all(target_arch = "loongarch64", feature = "nightly"))]
+    {
+        if std::arch::is_loongarch_feature_detected!("lasx") {
+            unsafe { simd::lasx::format_string(value, dst, slow) }
+        } else if std::arch::is_loongarch_feature_detected!("lsx") {
+            unsafe { simd::lsx::format_string(value, dst, slow) }
+        } else {
+            simd::fallback::format_string(value, dst, slow)
+        }
+    }
+
+    #[cfg(all(target_arch = "powerpc64", feature = "nightly"))]
+    {
+        if std::arch::is_powerpc64_feature_detected!("power8-vector") {
+            unsafe { simd::vsx::format_string_power8(value, dst, slow) }
+        } else if std::arch::is_powerpc64_feature_detected!("vsx") {
+            unsafe { simd::vsx::format_string(value, dst, slow) }
+        } else {
+            simd::fallback::format_string(value, dst, slow)
+        }
+    }
+
+    #[cfg(all(target_arch = "s390x", feature = "nightly"))]
+    {
+        if std::arch::is_s390x_feature_detected!("vector-enhancements-1") {
+            unsafe { simd::s390x_vector::format_string_bperm(value, dst, slow) }
+        } else if std::arch::is_s390x_feature_detected!("vector") {
+            unsafe { simd::s390x_vector::format_string(value, dst, slow) }
         } else {
-            simd::v128::format_string(value, dst)
+            simd::fallback::format_string(value, dst, slow)
         }
     }
 
-    #[cfg(not(any(target_arch = "aarch64", target_arch = "x86", target_arch = "x86_64")))]
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
     {
-        simd::v128::format_string(value, dst)
+        simd::wasm32::format_string(value, dst, slow)
+    }
+
+    #[cfg(not(any(
+        target_arch = "aarch64",
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "riscv64",
+        all(target_arch = "loongarch64", feature = "nightly"),
+        all(target_arch = "powerpc64", feature = "nightly"),
+        all(target_arch = "s390x", feature = "nightly"),
+        all(target_arch = "wasm32", target_feature = "simd128")
+    )))]
+    {
+        simd::fallback::format_string(value, dst, slow)
     }
 }
 
+/// Escapes `value`, including the surrounding `"`.
+///
+/// # Panics
+///
+/// Panics if the `value.len() * 6 + 35` bytes of scratch space the kernels
+/// need overflow `usize`, which only inputs over ~715 MB can do on 32-bit
+/// targets.
 pub fn escape(value: &str) -> String {
-    let capacity = value.len() * 6 + 32 + 3;
-    let mut buf = Vec::with_capacity(capacity);
+    let capacity = scratch_len(value.len());
+    // SAFETY: the buffer holds the scratch space `escape_in_buf` needs.
+    unsafe { escape_in_buf(value, Vec::with_capacity(capacity)) }
+}
+
+/// Like [`escape`], but returns an error instead of aborting when the scratch
+/// space cannot be allocated, or its size overflows `usize`.
+pub fn try_escape(value: &str) -> Result<String, TryReserveError> {
+    let capacity = checked_scratch_len(value.len()).ok_or_else(capacity_overflow)?;
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(capacity)?;
+    // SAFETY: the buffer holds the scratch space `escape_in_buf` needs.
+    Ok(unsafe { escape_in_buf(value, buf) })
+}
+
+/// Like [`escape`], but allocates the output, and the scratch space the
+/// kernels need, with `alloc`.
+///
+/// Returns bytes because there is no `String` for custom allocators; they are
+/// valid UTF-8. To append to an existing buffer, pass it to [`escape_to`].
+///
+/// # Panics
+///
+/// Panics like [`escape`].
+#[cfg(feature = "allocator-api2")]
+pub fn escape_in<A: allocator_api2::alloc::Allocator>(
+    value: &str,
+    alloc: A,
+) -> allocator_api2::vec::Vec<u8, A> {
+    let capacity = scratch_len(value.len());
+    let mut buf = allocator_api2::vec::Vec::with_capacity_in(capacity, alloc);
+    // SAFETY: the `capacity` bytes are the scratch space the kernel needs, and
+    // it returns how many of them it wrote.
+    unsafe {
+        let spare = buf.spare_capacity_mut();
+        let cnt = format_string(value, &mut *(spare as *mut [MaybeUninit<u8>] as *mut [u8]));
+        buf.set_len(cnt);
+    }
+    buf
+}
+
+/// Escapes `value` into the empty `buf`.
+///
+/// # Safety
+///
+/// `buf` must have a capacity of at least `checked_scratch_len(value.len())`.
+#[inline(always)]
+unsafe fn escape_in_buf(value: &str, mut buf: Vec<u8>) -> String {
+    let capacity = buf.capacity();
     #[allow(clippy::uninit_vec)]
     unsafe {
         buf.set_len(capacity)
@@ -334,30 +546,598 @@ pub fn escape(value: &str) -> String {
     unsafe { String::from_utf8_unchecked(buf) }
 }
 
+/// The aarch64 kernels by name, for `benches/escape.rs` to compare SVE with
+/// NEON on the same CPU. Not public API.
+#[doc(hidden)]
+#[cfg(target_arch = "aarch64")]
+pub mod __aarch64_kernels {
+    use super::{Quote, scratch_len, simd};
+
+    /// Escapes `value` with the `"neon"`, `"sve"` or `"sve2"` kernel, or
+    /// returns `None` if the CPU lacks it.
+    pub fn escape_with(kernel: &str, value: &str) -> Option<String> {
+        let format_string: unsafe fn(&str, &mut [u8], Quote) -> usize = match kernel {
+            "neon" if std::arch::is_aarch64_feature_detected!("neon") => simd::neon::format_string,
+            "sve" if std::arch::is_aarch64_feature_detected!("sve") => simd::sve::format_string,
+            "sve2" if std::arch::is_aarch64_feature_detected!("sve2") => {
+                simd::sve::format_string_sve2
+            }
+            _ => return None,
+        };
+        let mut buf = Vec::with_capacity(scratch_len(value.len()));
+        #[allow(clippy::uninit_vec)]
+        unsafe {
+            buf.set_len(buf.capacity())
+        };
+        // SAFETY: the CPU has the kernel's features, and `buf` is its scratch.
+        let cnt = unsafe { format_string(value, &mut buf, Quote) };
+        unsafe { buf.set_len(cnt) };
+        Some(unsafe { String::from_utf8_unchecked(buf) })
+    }
+}
+
+/// Returns the length of `escape(value)`, i.e. `value` escaped and quoted.
+///
+/// Counts without writing anything; the loop is written so that LLVM
+/// vectorizes it with the target's baseline SIMD.
+///
+/// # Panics
+///
+/// Panics if the length overflows `usize`, which is only possible on 32-bit
+/// targets.
+pub fn escaped_len(value: &str) -> usize {
+    escaped_bytes_len(value.as_bytes())
+        .checked_add(2)
+        .expect(LEN_OVERFLOW)
+}
+
+/// Bytes per round of [`escaped_bytes_len`]'s `u8` lanes, which it flushes
+/// before they can overflow: every byte adds at most 5.
+const COUNT_LANES: usize = 32;
+const COUNT_BLOCK: usize = COUNT_LANES * (u8::MAX as usize / 5);
+
+/// Returns the length of `bytes` escaped, without the quotes.
+#[inline]
+fn escaped_bytes_len(bytes: &[u8]) -> usize {
+    #[inline(always)]
+    fn extra(b: u8) -> u8 {
+        let control = (b < 0x20) as u8;
+        let short = matches!(b, b'\x08' | b'\t' | b'\n' | b'\x0c' | b'\r') as u8;
+        let quote = (b == b'"') as u8 | (b == b'\\') as u8;
+        // `\u00XX` adds 5 bytes, `\n` and friends 1, `\"` and `\\` 1.
+        control * 5 - short * 4 + quote
+    }
+
+    let mut len = bytes.len();
+    let mut blocks = bytes.chunks_exact(COUNT_BLOCK);
+    for block in &mut blocks {
+        let mut acc = [0u8; COUNT_LANES];
+        for lanes in block.chunks_exact(COUNT_LANES) {
+            for (acc, &b) in acc.iter_mut().zip(lanes) {
+                *acc += extra(b);
+            }
+        }
+        len = len
+            .checked_add(acc.iter().map(|&n| n as usize).sum::<usize>())
+            .expect(LEN_OVERFLOW);
+    }
+    len.checked_add(
+        blocks
+            .remainder()
+            .iter()
+            .map(|&b| extra(b) as usize)
+            .sum::<usize>(),
+    )
+    .expect(LEN_OVERFLOW)
+}
+
+/// Escapes `value` (including the surrounding `"`) into at most
+/// `max_output_bytes`, cutting it short and appending `suffix`, escaped, if it
+/// does not fit. Returns the output and whether `value` was cut.
+///
+/// The cut never splits a char or an escape sequence, and keeps as much of
+/// `value` as fits. Only about `max_output_bytes` of `value` are looked at to
+/// find it.
+///
+/// # Panics
+///
+/// Panics if the escaped `suffix` alone does not fit in `max_output_bytes`.
+pub fn escape_truncated(value: &str, max_output_bytes: usize, suffix: &str) -> (String, bool) {
+    let suffix_len = escaped_len(suffix);
+    assert!(
+        suffix_len <= max_output_bytes,
+        "the escaped suffix does not fit in `max_output_bytes`"
+    );
+    // Room for the escaped `value` without the suffix, and with it.
+    let whole = max_output_bytes - 2;
+    let cut_budget = max_output_bytes - suffix_len;
+
+    // Count whole blocks while they fit even with the suffix, then go byte by
+    // byte to find the last char boundary that does, and to tell whether all of
+    // `value` fits without the suffix.
+    let bytes = value.as_bytes();
+    let mut len = 0;
+    let mut pos = 0;
+    while let Some(block) = bytes.get(pos..pos + COUNT_BLOCK) {
+        let block_len = escaped_bytes_len(block);
+        if len + block_len > cut_budget {
+            break;
+        }
+        len += block_len;
+        pos += COUNT_BLOCK;
+    }
+    // The blocks can end inside a char: cut no later than the start of it.
+    let mut cut = pos;
+    while !value.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    for (at, &b) in bytes.iter().enumerate().skip(pos) {
+        if b & 0xc0 != 0x80 && len <= cut_budget {
+            cut = at;
+        }
+        len += QUOTE_TAB[b as usize].0.max(1) as usize;
+        if len > whole {
+            let mut dst = Vec::with_capacity(max_output_bytes);
+            escape_concat([&value[..cut], suffix], &mut dst);
+            // SAFETY: the escaped output is valid UTF-8.
+            return (unsafe { String::from_utf8_unchecked(dst) }, true);
+        }
+    }
+    (escape(value), false)
+}
+
+/// Like [`escape`], but allocates exactly: the result has `capacity() ==
+/// len()`.
+///
+/// [`escape`] reserves the worst case of `value.len() * 6 + 35` bytes and keeps
+/// it, which is 6x the memory for mostly clean text. This first counts the
+/// output with [`escaped_len`], then escapes straight into the allocation in
+/// windows that leave the kernels their scratch room, so it costs an extra
+/// read of `value` instead.
+///
+/// # Panics
+///
+/// Panics if the length overflows `usize`, like [`escaped_len`].
+pub fn escape_exact(value: &str) -> String {
+    /// A `Vec` that is never grown, because it already has room for the
+    /// output, if not for the kernels' scratch space.
+    struct Exact(Vec<u8>);
+
+    unsafe impl EscapeSink for Exact {
+        fn reserve_spare(&mut self, _additional: usize) -> &mut [MaybeUninit<u8>] {
+            self.0.spare_capacity_mut()
+        }
+
+        unsafe fn commit(&mut self, n: usize) {
+            unsafe { self.0.commit(n) }
+        }
+    }
+
+    let len = escaped_len(value);
+    let mut buf = Exact(Vec::with_capacity(len));
+    escape_to(value, &mut buf);
+    debug_assert_eq!(buf.0.len(), len);
+    // SAFETY: the escaped output is valid UTF-8.
+    unsafe { String::from_utf8_unchecked(buf.0) }
+}
+
+thread_local! {
+    /// The buffer `with_escaped` reuses, taken out while in use.
+    static SCRATCH: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+/// Capacity `with_escaped` keeps between calls; enough for inputs of about
+/// 10 KiB.
+const SCRATCH_KEEP: usize = 64 * 1024;
+
+/// Escapes `value` (including the surrounding `"`) into a buffer reused across
+/// calls on the current thread and passes it to `f`, saving the allocation of
+/// [`escape`] for short-lived output.
+///
+/// The buffer grows like [`escape_into`]'s destination and is shrunk back
+/// after unusually large inputs. Calling `with_escaped` again from `f` works,
+/// but allocates.
+pub fn with_escaped<R>(value: &str, f: impl FnOnce(&str) -> R) -> R {
+    let mut buf = SCRATCH.try_with(Cell::take).unwrap_or_default();
+    buf.clear();
+    escape_into(value, &mut buf);
+    // SAFETY: the escaped output is valid UTF-8.
+    let r = f(unsafe { std::str::from_utf8_unchecked(&buf) });
+    if buf.capacity() > SCRATCH_KEEP {
+        buf.clear();
+        buf.shrink_to(SCRATCH_KEEP);
+    }
+    // Gone if the thread is exiting, then `buf` is simply dropped.
+    let _ = SCRATCH.try_with(|scratch| scratch.set(buf));
+    r
+}
+
+/// Escapes `value` in place, including the surrounding `"`.
+///
+/// Counts the output with [`escaped_len`] and grows `value` once to hold it,
+/// then escapes it back to front a piece at a time through a stack buffer, so
+/// it needs no scratch space proportional to `value`.
+///
+/// # Panics
+///
+/// Panics if the length overflows `usize`, like [`escaped_len`].
+pub fn escape_in_place(value: &mut String) {
+    let len = value.len();
+    let escaped = escaped_len(value);
+    // SAFETY: the bytes are only rearranged into their escaped form below,
+    // which is valid UTF-8, and nothing in between can panic.
+    let buf = unsafe { value.as_mut_vec() };
+    buf.reserve_exact(escaped - len);
+    let ptr = buf.as_mut_ptr();
+
+    // The input left to escape is `..src`, and its output goes right before
+    // `dst`. Once they are one byte apart, what is left has nothing to escape.
+    let mut src = len;
+    let mut dst = escaped - 1;
+    let mut scratch = [MaybeUninit::<u8>::uninit(); PIECE * 6 + 32 + 3];
+    while dst > src + 1 {
+        let mut start = src.saturating_sub(PIECE);
+        // SAFETY: `start < src <= len`, within the initialized bytes.
+        while unsafe { *ptr.add(start) } & 0xc0 == 0x80 {
+            start += 1;
+        }
+        // SAFETY: `start..src` is a whole number of chars of `value`, which is
+        // only overwritten once the piece is escaped, and its output fits in
+        // the room `reserve_exact` made: it ends where the rest's starts. The
+        // kernel only reads back the scratch bytes it wrote.
+        unsafe {
+            let piece = std::str::from_utf8_unchecked(std::slice::from_raw_parts(
+                ptr.add(start),
+                src - start,
+            ));
+            let scratch =
+                std::slice::from_raw_parts_mut(scratch.as_mut_ptr().cast::<u8>(), scratch.len());
+            let cnt = format_string(piece, scratch);
+            dst -= cnt - 2;
+            std::ptr::copy_nonoverlapping(scratch.as_ptr().add(1), ptr.add(dst), cnt - 2);
+        }
+        src = start;
+    }
+    debug_assert_eq!(dst, src + 1);
+    // SAFETY: the `src` bytes left move one over for the opening quote, and
+    // the `escaped` bytes are all written then.
+    unsafe {
+        std::ptr::copy(ptr, ptr.add(1), src);
+        *ptr = b'"';
+        *ptr.add(escaped - 1) = b'"';
+        buf.set_len(escaped);
+    }
+}
+
 /// Escapes `value` (including the surrounding `"`) and appends the result to
 /// `dst`, growing `dst` as needed.
+///
+/// Large inputs are escaped 64 KiB at a time, so `dst` only ever needs room for
+/// the real output plus the worst case of one window rather than of all of
+/// `value`.
 pub fn escape_into<S: AsRef<str>>(value: S, dst: &mut Vec<u8>) {
-    let value = value.as_ref();
-
-    // The SIMD kernels perform full-register speculative stores and copy 8 bytes
-    // per escape, so they need up to `len * 6 + 32 + 3` scratch bytes past the
-    // current end regardless of the final output length. Reserve that up front
-    // so the unchecked writes below can never exceed the allocation. `reserve`
-    // is effectively free when the caller already sized `dst` large enough.
-    dst.reserve(value.len() * 6 + 32 + 3);
-    let old_len = dst.len();
-
-    // SAFETY: the `reserve` above guarantees `dst.capacity() - old_len` is at
-    // least `value.len() * 6 + 32 + 3`, which upper-bounds every store
-    // `format_string` performs. It writes valid UTF-8 and returns the number of
-    // bytes written, which we then commit as the new length.
-    unsafe {
-        // Get a slice that includes the spare capacity
-        let spare =
-            std::slice::from_raw_parts_mut(dst.as_mut_ptr().add(old_len), dst.capacity() - old_len);
-        let cnt = format_string(value, spare);
-        dst.set_len(old_len + cnt);
+    escape_to(value.as_ref(), dst);
+}
+
+/// Escapes the concatenation of `fragments` as one JSON string (including the
+/// surrounding `"`) and appends it to `dst`, without joining them first.
+pub fn escape_concat<'a>(fragments: impl IntoIterator<Item = &'a str>, dst: &mut Vec<u8>) {
+    let mut escaper = Escaper::new(dst, grow, Quote);
+    for fragment in fragments {
+        let Ok(()) = escaper.push(fragment);
+    }
+    let Ok(()) = escaper.finish();
+}
+
+/// Escapes the formatted `args` as one JSON string (including the surrounding
+/// `"`) and appends it to `dst`, without formatting into a `String` first.
+///
+/// # Panics
+///
+/// Panics if a formatting trait implementation returns an error, like
+/// `format!` does.
+pub fn escape_fmt(args: fmt::Arguments<'_>, dst: &mut Vec<u8>) {
+    /// Escapes what is written to it, gathering short pieces first: each
+    /// call into the kernels costs about as much as escaping a few dozen bytes.
+    struct Writer<'a, S: ?Sized, R> {
+        escaper: Escaper<'a, S, R>,
+        buf: [u8; 256],
+        len: usize,
+    }
+
+    impl<S: EscapeSink + ?Sized, R: FnMut(&mut S, usize) -> Result<(), Infallible>> Writer<'_, S, R> {
+        fn flush(&mut self) {
+            // SAFETY: `buf` holds whole `&str`s.
+            let gathered = unsafe { std::str::from_utf8_unchecked(&self.buf[..self.len]) };
+            let Ok(()) = self.escaper.push(gathered);
+            self.len = 0;
+        }
+    }
+
+    impl<S: EscapeSink + ?Sized, R: FnMut(&mut S, usize) -> Result<(), Infallible>> fmt::Write
+        for Writer<'_, S, R>
+    {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            if self.len + s.len() > self.buf.len() {
+                self.flush();
+            }
+            if s.len() > self.buf.len() {
+                let Ok(()) = self.escaper.push(s);
+            } else {
+                self.buf[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+                self.len += s.len();
+            }
+            Ok(())
+        }
+    }
+
+    if let Some(value) = args.as_str() {
+        return escape_into(value, dst);
+    }
+    let mut writer = Writer {
+        escaper: Escaper::new(dst, grow, Quote),
+        buf: [0; 256],
+        len: 0,
+    };
+    fmt::write(&mut writer, args).expect("a formatting trait implementation returned an error");
+    writer.flush();
+    let Ok(()) = writer.escaper.finish();
+}
+
+/// Like [`escape_into`], but returns an error instead of aborting when `dst`
+/// cannot grow. `dst` keeps its contents then, though it may have grown.
+pub fn try_escape_into<S: AsRef<str>>(value: S, dst: &mut Vec<u8>) -> Result<(), TryReserveError> {
+    let len = dst.len();
+    escape_to_with(value.as_ref(), dst, Vec::try_reserve, Quote).inspect_err(|_| dst.truncate(len))
+}
+
+/// Escapes `value` (including the surrounding `"`) and appends the result to
+/// `sink`, like [`escape_into`] does to a `Vec<u8>`.
+///
+/// # Panics
+///
+/// Panics if `sink` cannot grow and runs out of room. What was escaped up to
+/// that point is left in it, ending on a char boundary.
+pub fn escape_to<S: EscapeSink + ?Sized>(value: &str, sink: &mut S) {
+    let Ok(()) = escape_to_with(value, sink, grow, Quote);
+}
+
+/// The body of [`escape_to`] and [`try_escape_into`], the latter of which
+/// makes room with `reserve` before [`EscapeSink::reserve_spare`] is asked to.
+/// The kernels escape with `slow`.
+#[inline(always)]
+fn escape_to_with<S: EscapeSink + ?Sized, E, P: SlowPath>(
+    value: &str,
+    sink: &mut S,
+    mut reserve: impl FnMut(&mut S, usize) -> Result<(), E>,
+    slow: P,
+) -> Result<(), E> {
+    // Most inputs are escaped in one go.
+    if value.len() <= WINDOW {
+        let scratch = scratch_len(value.len());
+        reserve(sink, scratch)?;
+        let spare = sink.reserve_spare(scratch);
+        if spare.len() >= scratch {
+            // SAFETY: as in `escape_piece`, the kernel gets the scratch space
+            // it may scribble over, and writes valid UTF-8.
+            unsafe {
+                let dst = &mut *(spare as *mut [MaybeUninit<u8>] as *mut [u8]);
+                let cnt = format_string_with(value, dst, slow);
+                sink.commit(cnt);
+            }
+            return Ok(());
+        }
+    }
+
+    let mut escaper = Escaper::new(sink, reserve, slow);
+    escaper.push(value)?;
+    escaper.finish()
+}
+
+/// The `reserve` of [`Escaper`]s whose sinks grow in
+/// [`EscapeSink::reserve_spare`], and abort when they cannot.
+fn grow<S: ?Sized>(_: &mut S, _: usize) -> Result<(), Infallible> {
+    Ok(())
+}
+
+/// Input bytes per kernel call. Sinks that cannot make room for the kernels'
+/// `len * 6 + 32 + 3` bytes of scratch space for a whole window get smaller
+/// pieces, escaped through a stack buffer if need be.
+const WINDOW: usize = 64 * 1024;
+
+/// Input bytes per kernel call when escaping through a stack buffer.
+const PIECE: usize = 1024;
+
+/// Escapes a string that arrives in pieces to the end of a sink, as one quoted
+/// string.
+///
+/// Makes room with `reserve` before [`EscapeSink::reserve_spare`] is asked to,
+/// and escapes with `slow`.
+struct Escaper<'a, S: ?Sized, R, P = Quote> {
+    sink: &'a mut S,
+    reserve: R,
+    slow: P,
+    held: Held,
+}
+
+/// The last char of the output so far, which is not committed yet: the next
+/// piece puts its opening quote over it, so that its output starts over the
+/// previous closing quote, and the char is written back afterwards. Starts out
+/// as the opening quote.
+struct Held {
+    bytes: [u8; 4],
+    len: usize,
+}
+
+impl<'a, S, R, E, P> Escaper<'a, S, R, P>
+where
+    S: EscapeSink + ?Sized,
+    R: FnMut(&mut S, usize) -> Result<(), E>,
+    P: SlowPath,
+{
+    fn new(sink: &'a mut S, reserve: R, slow: P) -> Self {
+        Escaper {
+            sink,
+            reserve,
+            slow,
+            held: Held {
+                bytes: [b'"', 0, 0, 0],
+                len: 1,
+            },
+        }
+    }
+
+    fn push(&mut self, value: &str) -> Result<(), E> {
+        if value.is_empty() {
+            return Ok(());
+        }
+        // Only used for sinks without room for a window's scratch space.
+        let mut buf = [MaybeUninit::uninit(); PIECE * 6 + 32 + 3];
+        for window in windows(value, WINDOW) {
+            // The kernels' scratch space, after the held char.
+            let room = scratch_len(window.len()) + self.held.len;
+            (self.reserve)(self.sink, room)?;
+            if self.sink.reserve_spare(room).len() >= room {
+                escape_piece(window, self.sink, &mut self.held, &mut buf, self.slow);
+            } else {
+                for piece in windows(window, PIECE) {
+                    escape_piece(piece, self.sink, &mut self.held, &mut buf, self.slow);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends `escaped`, an ASCII escape sequence such as `\u003c`, as is.
+    fn push_escaped(&mut self, escaped: &[u8]) -> Result<(), E> {
+        assert!(escaped.is_ascii());
+        let (&last, rest) = escaped.split_last().expect("empty escape");
+        let n = self.held.len + rest.len();
+        (self.reserve)(self.sink, n)?;
+        let spare = self.sink.reserve_spare(n);
+        assert!(spare.len() >= n, "`EscapeSink` ran out of room");
+        let held = &self.held.bytes[..self.held.len];
+        for (dst, &b) in spare.iter_mut().zip(held.iter().chain(rest)) {
+            dst.write(b);
+        }
+        // SAFETY: the held char and the escape were just written.
+        unsafe { self.sink.commit(n) };
+        self.held = Held {
+            bytes: [last, 0, 0, 0],
+            len: 1,
+        };
+        Ok(())
+    }
+
+    /// Commits the held char and the closing quote.
+    fn finish(mut self) -> Result<(), E> {
+        let Held { bytes, len } = self.held;
+        (self.reserve)(self.sink, len + 1)?;
+        let spare = self.sink.reserve_spare(len + 1);
+        assert!(spare.len() > len, "`EscapeSink` ran out of room");
+        for (dst, &b) in spare.iter_mut().zip(bytes[..len].iter().chain([&b'"'])) {
+            dst.write(b);
+        }
+        // SAFETY: the held char and the quote were just written.
+        unsafe { self.sink.commit(len + 1) };
+        Ok(())
+    }
+}
+
+/// Escapes `piece` to the end of `sink`, behind the `held` char, and holds
+/// back the new last char. Goes through `buf` if `sink` has no room for the
+/// kernels' scratch space.
+#[inline(always)]
+fn escape_piece<S: EscapeSink + ?Sized, P: SlowPath>(
+    piece: &str,
+    sink: &mut S,
+    held: &mut Held,
+    buf: &mut [MaybeUninit<u8>; PIECE * 6 + 32 + 3],
+    slow: P,
+) {
+    let scratch = scratch_len(piece.len());
+    let spare = sink.reserve_spare(scratch);
+    // The held char, then the opening quote over its last byte.
+    let start = held.len - 1;
+    let room = spare.len().saturating_sub(start);
+    let cnt = if room >= scratch {
+        // SAFETY: the kernel gets the `scratch` bytes it may scribble over.
+        // It only writes to them, and reads back just what it wrote.
+        unsafe {
+            let dst = spare.as_mut_ptr().add(start).cast::<u8>();
+            format_string_with(piece, std::slice::from_raw_parts_mut(dst, scratch), slow)
+        }
+    } else {
+        assert!(piece.len() <= PIECE);
+        // SAFETY: as above, the kernel only reads back what it wrote.
+        let buf =
+            unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr().cast::<u8>(), buf.len()) };
+        let cnt = format_string_with(piece, buf, slow);
+        assert!(cnt <= room, "`EscapeSink` ran out of room");
+        for (dst, &b) in spare[start..start + cnt].iter_mut().zip(&buf[..cnt]) {
+            dst.write(b);
+        }
+        cnt
+    };
+    for (dst, &b) in spare.iter_mut().zip(&held.bytes[..held.len]) {
+        dst.write(b);
+    }
+
+    // Back to the start of the last char before the closing quote, which is
+    // at worst the held one.
+    let end = start + cnt - 1;
+    let mut at = end - 1;
+    // SAFETY: everything up to `end` was written above.
+    while unsafe { spare[at].assume_init() } & 0xc0 == 0x80 {
+        at -= 1;
+    }
+    held.len = end - at;
+    for (b, src) in held.bytes.iter_mut().zip(&spare[at..end]) {
+        *b = unsafe { src.assume_init() };
     }
+    // SAFETY: the bytes up to `at` are initialized, and valid UTF-8 since `at`
+    // is a char boundary of the escaped output.
+    unsafe { sink.commit(at) };
+}
+
+const LEN_OVERFLOW: &str = "escaped length overflows `usize`";
+
+/// Returns the `len * 6 + 32 + 3` bytes of room the kernels need to escape
+/// `len` bytes, or `None` if that overflows `usize`.
+///
+/// Every escape is at most 6 bytes, and the kernels may store a full register
+/// past the end of the output.
+#[inline(always)]
+fn checked_scratch_len(len: usize) -> Option<usize> {
+    len.checked_mul(6)?.checked_add(32 + 3)
+}
+
+#[inline(always)]
+fn scratch_len(len: usize) -> usize {
+    checked_scratch_len(len).expect(LEN_OVERFLOW)
+}
+
+/// The error `try_reserve` reports for sizes that overflow `usize`, which
+/// cannot be built directly.
+#[cold]
+fn capacity_overflow() -> TryReserveError {
+    Vec::<u8>::new().try_reserve(usize::MAX).unwrap_err()
+}
+
+/// Splits `value` into pieces of at most `size` bytes (`size >= 4`) that end on
+/// char boundaries. An empty `value` gives one empty piece.
+fn windows(value: &str, size: usize) -> impl Iterator<Item = &str> {
+    let mut rest = Some(value);
+    std::iter::from_fn(move || {
+        let value = rest?;
+        let mut end = value.len().min(size);
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (window, tail) = value.split_at(end);
+        rest = (!tail.is_empty()).then_some(tail);
+        Some(window)
+    })
 }
 
 #[cfg(test)]
@@ -571,6 +1351,502 @@ mod tests {
         assert_eq!(escape(&pattern3), serde_json::to_string(&pattern3).unwrap());
     }
 
+    #[test]
+    fn test_escape_exact() {
+        // Long enough to span several windows, with multi-byte chars and
+        // escapes landing on the window boundaries.
+        let long: Vec<String> = ["a", "中", "😊", "\"", "\x00"]
+            .iter()
+            .flat_map(|c| {
+                [
+                    c.repeat(1023),
+                    c.repeat(5000),
+                    format!("a{}", c.repeat(3000)),
+                ]
+            })
+            .collect();
+        for input in mixed_inputs().chain(long) {
+            let escaped = escape_exact(&input);
+            assert_eq!(escaped, escape(&input), "input: {:?}", input);
+            assert_eq!(escaped.capacity(), escaped.len());
+            assert_eq!(escaped_len(&input), escaped.len());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too slow under Miri")]
+    fn test_escape_to_string() {
+        let long: Vec<String> = ["a", "中", "😊", "\"", "\x00"]
+            .iter()
+            .map(|c| c.repeat(70_000 / c.len()))
+            .collect();
+        for input in mixed_inputs().chain(long) {
+            let mut dst = String::from("é");
+            escape_to(&input, &mut dst);
+            assert_eq!(dst[2..], escape(&input), "input: {:?}", input);
+        }
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_escape_to_bytes_mut() {
+        for input in mixed_inputs() {
+            let mut dst = bytes::BytesMut::new();
+            escape_to(&input, &mut dst);
+            assert_eq!(dst, escape(&input).as_bytes(), "input: {:?}", input);
+        }
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn test_escape_to_smallvec() {
+        for input in mixed_inputs() {
+            let mut dst = smallvec::SmallVec::<[u8; 64]>::new();
+            escape_to(&input, &mut dst);
+            assert_eq!(dst[..], *escape(&input).as_bytes(), "input: {:?}", input);
+        }
+    }
+
+    #[cfg(feature = "arrayvec")]
+    #[test]
+    fn test_escape_to_arrayvec() {
+        // Far less room than the kernels' scratch space, so the output goes
+        // through the stack buffer, across pieces for the longer inputs.
+        let long: Vec<String> = ["a", "中", "\"", "\x00"]
+            .iter()
+            .map(|c| c.repeat(2000 / c.len()))
+            .collect();
+        for input in mixed_inputs().chain(long) {
+            let expected = escape(&input);
+            if expected.len() > 4096 {
+                continue;
+            }
+            let mut dst = arrayvec::ArrayVec::<u8, 4096>::new();
+            escape_to(&input, &mut dst);
+            assert_eq!(dst[..], *expected.as_bytes(), "input: {:?}", input);
+
+            // An exact fit.
+            let mut dst = arrayvec::ArrayVec::<u8, 4096>::new();
+            dst.extend(std::iter::repeat_n(b' ', 4096 - expected.len()));
+            escape_to(&input, &mut dst);
+            assert_eq!(dst[4096 - expected.len()..], *expected.as_bytes());
+        }
+    }
+
+    #[cfg(feature = "arrayvec")]
+    #[test]
+    fn test_escape_to_arrayvec_out_of_room() {
+        let input = "中\"".repeat(1000);
+        let mut dst = arrayvec::ArrayVec::<u8, 3000>::new();
+        let escaped = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            escape_to(&input, &mut dst);
+        }));
+        assert!(escaped.is_err());
+        // A prefix of the output, cut at a char boundary.
+        let prefix = std::str::from_utf8(&dst).unwrap();
+        assert!(!prefix.is_empty());
+        assert!(escape(&input).starts_with(prefix));
+    }
+
+    #[test]
+    fn test_escape_in_place() {
+        // Long enough for several pieces, with multi-byte chars and escapes
+        // landing on their boundaries, and escapes only at either end.
+        let long: Vec<String> = ["a", "中", "😊", "\"", "\x00"]
+            .iter()
+            .flat_map(|c| {
+                [
+                    c.repeat(1023),
+                    c.repeat(5000),
+                    format!("a{}", c.repeat(3000)),
+                ]
+            })
+            .chain([
+                "\"".to_string() + &"a".repeat(5000),
+                "a".repeat(5000) + "\"",
+            ])
+            .collect();
+        for input in mixed_inputs().chain(long) {
+            let mut escaped = String::from(input.as_str());
+            escape_in_place(&mut escaped);
+            assert_eq!(escaped, escape(&input), "input: {:?}", input);
+            // Grown once, exactly.
+            assert_eq!(escaped.capacity(), escaped.len());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too slow under Miri")]
+    fn test_with_escaped() {
+        for input in mixed_inputs() {
+            with_escaped(&input, |escaped| assert_eq!(escaped, escape(&input)));
+        }
+        // Short inputs reuse the buffer.
+        let first = with_escaped("a\"b", |escaped| escaped.as_ptr() as usize);
+        let second = with_escaped("c\nd", |escaped| escaped.as_ptr() as usize);
+        assert_eq!(first, second);
+        // Nested calls get a buffer of their own.
+        with_escaped("outer", |outer| {
+            with_escaped("inner", |inner| assert_eq!(inner, "\"inner\""));
+            assert_eq!(outer, "\"outer\"");
+        });
+        // A large input grows it, but not for good.
+        let large = "\"".repeat(1 << 20);
+        with_escaped(&large, |escaped| assert_eq!(escaped, escape(&large)));
+        let capacity = SCRATCH.with(|scratch| {
+            let buf = scratch.take();
+            let capacity = buf.capacity();
+            scratch.set(buf);
+            capacity
+        });
+        assert!(capacity <= SCRATCH_KEEP);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too slow under Miri")]
+    fn test_escape_concat() {
+        let inputs: Vec<String> = mixed_inputs().collect();
+        // Adjacent pairs, split at every char boundary.
+        for pair in inputs.windows(2) {
+            let joined = pair.concat();
+            for (at, _) in joined
+                .char_indices()
+                .chain([(joined.len(), ' ')])
+                .step_by(7)
+            {
+                let (head, tail) = joined.split_at(at);
+                let mut dst = b"[".to_vec();
+                escape_concat(["", head, "", tail], &mut dst);
+                assert_eq!(dst[1..], *escape(&joined).as_bytes(), "{head:?} + {tail:?}");
+            }
+        }
+        let mut dst = Vec::new();
+        escape_concat([], &mut dst);
+        assert_eq!(dst, b"\"\"");
+        // Fragments longer than a window.
+        let long = ["中".repeat(30_000), "\"".repeat(70_000), "a".repeat(100)];
+        let mut dst = Vec::new();
+        escape_concat(long.iter().map(String::as_str), &mut dst);
+        assert_eq!(dst, escape(&long.concat()).as_bytes());
+    }
+
+    #[test]
+    fn test_escape_fmt() {
+        let mut dst = Vec::new();
+        escape_fmt(format_args!("plain \"text\""), &mut dst);
+        assert_eq!(dst, br#""plain \"text\"""#);
+        for input in mixed_inputs() {
+            let mut dst = b"[".to_vec();
+            escape_fmt(format_args!("{input}:{}\n{input:?}", input.len()), &mut dst);
+            let formatted = format!("{input}:{}\n{input:?}", input.len());
+            assert_eq!(dst[1..], *escape(&formatted).as_bytes(), "input: {input:?}");
+        }
+    }
+
+    /// Checks that `escape_truncated` keeps the longest prefix of chars of
+    /// `input` that fits in `max` with `suffix`.
+    fn check_truncated(input: &str, escaped: &str, max: usize, suffix: &str) {
+        let (output, truncated) = escape_truncated(input, max, suffix);
+        assert!(output.len() <= max, "{input:?} into {max}");
+        assert_eq!(truncated, escaped.len() > max, "{input:?} into {max}");
+        if !truncated {
+            assert_eq!(output, escaped);
+            return;
+        }
+        let decoded: String = serde_json::from_str(&output).unwrap();
+        let prefix = decoded.strip_suffix(suffix).unwrap();
+        assert!(input.starts_with(prefix), "{input:?} into {max}");
+        if let Some(next) = input[prefix.len()..].chars().next() {
+            let longer = format!("{prefix}{next}{suffix}");
+            assert!(escaped_len(&longer) > max, "{input:?} into {max}");
+        }
+        assert_eq!(output, escape(&format!("{prefix}{suffix}")));
+    }
+
+    #[test]
+    fn test_escape_truncated() {
+        let inputs = [
+            // Budgets landing inside `\u00XX`, `\n` and multi-byte chars.
+            "ab\u{1}cd\u{1f}\u{1f}e".to_string(),
+            "x\n\"中😊\\\u{0}y".to_string(),
+            "a".repeat(5000) + "\u{1}" + &"中".repeat(1000),
+            "\u{0}".repeat(2000),
+        ];
+        for input in mixed_inputs().step_by(7).chain(inputs) {
+            let escaped = escape(&input);
+            for suffix in ["", "…", "\"more\""] {
+                let suffix_len = escaped_len(suffix);
+                let budgets = (suffix_len..suffix_len + 40)
+                    .chain((escaped.len().saturating_sub(40)..escaped.len() + 2).step_by(3));
+                for max in budgets.filter(|&max| max >= suffix_len) {
+                    check_truncated(&input, &escaped, max, suffix);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too slow under Miri")]
+    fn test_escape_truncated_mid_block() {
+        // Multi-byte chars straddling the counting blocks, with budgets that
+        // run out around the end of each block.
+        for pad in 0..4 {
+            let input = ("a".repeat(COUNT_BLOCK - 1 - pad) + "中" + &"b".repeat(COUNT_BLOCK - 3))
+                .repeat(3)
+                + "😊";
+            let escaped = escape(&input);
+            for suffix in ["", "…"] {
+                for end in (1..4).map(|n| n * COUNT_BLOCK) {
+                    for max in end - 4..end + 8 {
+                        check_truncated(&input, &escaped, max, suffix);
+                    }
+                }
+            }
+        }
+        let input = "a".repeat(1631) + "中" + &"b".repeat(4000);
+        check_truncated(&input, &escape(&input), 1634, "");
+    }
+
+    #[test]
+    fn test_escape_with_policy() {
+        use ControlAction::*;
+        let input = "a\0b\u{1}\n\"中\0";
+        let cases = [
+            (
+                ControlPolicy::nul(Escape),
+                r#""a\u0000b\u0001\n\"中\u0000""#,
+            ),
+            (ControlPolicy::nul(Drop), r#""ab\u0001\n\"中""#),
+            (
+                ControlPolicy::nul(Replace),
+                "\"a\u{fffd}b\\u0001\\n\\\"中\u{fffd}\"",
+            ),
+            (ControlPolicy::c0(Escape), r#""a\u0000b\u0001\n\"中\u0000""#),
+            (ControlPolicy::c0(Drop), r#""ab\"中""#),
+            (
+                ControlPolicy::c0(Replace),
+                "\"a\u{fffd}b\u{fffd}\u{fffd}\\\"中\u{fffd}\"",
+            ),
+        ];
+        for (policy, expected) in cases {
+            assert_eq!(
+                escape_with_policy(input, policy).unwrap(),
+                expected,
+                "{policy:?}"
+            );
+        }
+
+        let err = escape_with_policy(input, ControlPolicy::nul(Reject)).unwrap_err();
+        assert_eq!((err.offset(), err.byte()), (1, 0));
+        assert_eq!(err.to_string(), "control character U+0000 at byte offset 1");
+        let err = escape_with_policy("中\"\t", ControlPolicy::c0(Reject)).unwrap_err();
+        assert_eq!((err.offset(), err.byte()), (4, b'\t'));
+        let mut dst = b"[".to_vec();
+        assert!(escape_into_with_policy(input, ControlPolicy::c0(Reject), &mut dst).is_err());
+        assert_eq!(dst, b"[");
+        // Escaped backslashes followed by what looks like a control's escape.
+        let lookalike = r"\n\u0000\";
+        for policy in [ControlPolicy::nul(Reject), ControlPolicy::c0(Reject)] {
+            assert_eq!(
+                escape_with_policy(lookalike, policy).unwrap(),
+                escape(lookalike)
+            );
+        }
+        assert_eq!(
+            escape_with_policy("\t\0", ControlPolicy::nul(Reject))
+                .unwrap_err()
+                .offset(),
+            1
+        );
+
+        // Inputs without the chosen controls escape as usual.
+        for input in mixed_inputs() {
+            for policy in [ControlPolicy::nul(Reject), ControlPolicy::nul(Drop)] {
+                if !input.contains('\0') {
+                    assert_eq!(escape_with_policy(&input, policy).unwrap(), escape(&input));
+                }
+            }
+            let stripped: String = input.chars().filter(|c| *c >= ' ').collect();
+            let dropped = escape_with_policy(&input, ControlPolicy::c0(Drop)).unwrap();
+            assert_eq!(dropped, escape(&stripped), "input: {input:?}");
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too slow under Miri")]
+    fn test_escape_with_policy_windows() {
+        // Controls past the first window and between long runs.
+        let long = format!(
+            "{}\0{}\u{1f}{}",
+            "中".repeat(30_000),
+            "\"".repeat(70_000),
+            "\0"
+        );
+        let replaced =
+            escape_with_policy(&long, ControlPolicy::c0(ControlAction::Replace)).unwrap();
+        assert_eq!(
+            replaced,
+            escape(&long.replace(['\0', '\u{1f}'], "\u{fffd}"))
+        );
+        let dropped = escape_with_policy(&long, ControlPolicy::c0(ControlAction::Drop)).unwrap();
+        assert_eq!(dropped, escape(&long.replace(['\0', '\u{1f}'], "")));
+        let err = escape_with_policy(&long, ControlPolicy::c0(ControlAction::Reject)).unwrap_err();
+        assert_eq!(err.offset(), 90_000);
+    }
+
+    #[test]
+    fn test_escape_with_policy_dense() {
+        // Blocks dense enough for the kernels to expand in-register, which
+        // they must not do for controls under a policy.
+        let dense = [
+            "\n\t\"\\".repeat(100),
+            "\0\r".repeat(100),
+            "a\u{1}\x08".repeat(100),
+        ];
+        for input in mixed_inputs().chain(dense) {
+            for chars in [ControlChars::Nul, ControlChars::C0] {
+                let policy = |action| ControlPolicy { chars, action };
+                let applies = |c: char| c == '\0' || chars == ControlChars::C0 && c < ' ';
+                let dropped: String = input.chars().filter(|&c| !applies(c)).collect();
+                let replaced: String = input
+                    .chars()
+                    .map(|c| if applies(c) { '\u{fffd}' } else { c })
+                    .collect();
+                let cases = [
+                    (ControlAction::Escape, input.as_str()),
+                    (ControlAction::Drop, &dropped),
+                    (ControlAction::Replace, &replaced),
+                ];
+                for (action, expected) in cases {
+                    assert_eq!(
+                        escape_with_policy(&input, policy(action)).unwrap(),
+                        escape(expected),
+                        "{action:?} {chars:?}: {input:?}"
+                    );
+                }
+                let rejected = escape_with_policy(&input, policy(ControlAction::Reject));
+                match input.find(applies) {
+                    Some(offset) => assert_eq!(rejected.unwrap_err().offset(), offset),
+                    None => assert_eq!(rejected.unwrap(), escape(&input)),
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(target_arch = "aarch64")]
+    fn test_aarch64_kernels() {
+        use super::__aarch64_kernels::escape_with;
+        // Long enough for the SVE kernels' four-vector blocks at any vector length.
+        let long = ["a".repeat(3000), "a\"".repeat(700), "中😊\n".repeat(300)].concat();
+        for input in mixed_inputs().chain([long]) {
+            for kernel in ["neon", "sve", "sve2"] {
+                if let Some(escaped) = escape_with(kernel, &input) {
+                    assert_eq!(escaped, escape(&input), "{kernel}: {input:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_scratch_len_overflow() {
+        // No input is this long, but on 32-bit targets ~715 MB is enough.
+        let max = (usize::MAX - 35) / 6;
+        assert_eq!(checked_scratch_len(max), Some(max * 6 + 35));
+        assert_eq!(checked_scratch_len(max + 1), None);
+        assert_eq!(checked_scratch_len(usize::MAX / 6 + 1), None);
+        assert_eq!(checked_scratch_len(usize::MAX), None);
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(max, 715_827_876);
+    }
+
+    #[cfg(feature = "portable-simd")]
+    #[test]
+    fn test_portable_simd_matches_dispatched_kernel() {
+        for input in mixed_inputs() {
+            let capacity = input.len() * 6 + 32 + 3;
+            let mut expected = vec![0; capacity];
+            let mut actual = vec![0; capacity];
+            let expected_len = format_string(&input, &mut expected);
+            let actual_len = simd::portable::format_string(&input, &mut actual, Quote);
+            assert_eq!(
+                actual[..actual_len],
+                expected[..expected_len],
+                "input: {input:?}"
+            );
+        }
+    }
+
+    // CI machines all have AVX2, so call the kernel directly.
+    #[cfg(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        not(target_feature = "avx2")
+    ))]
+    #[test]
+    fn test_ssse3() {
+        if !is_x86_feature_detected!("ssse3") {
+            return;
+        }
+        for input in mixed_inputs() {
+            assert_kernel_escapes(simd::ssse3::format_string, &input);
+        }
+    }
+
+    #[cfg(all(
+        target_arch = "x86_64",
+        any(
+            feature = "avx512",
+            all(target_feature = "avx512bw", target_feature = "avx512vl")
+        )
+    ))]
+    #[test]
+    fn test_avx512_vbmi2() {
+        if !(is_x86_feature_detected!("avx512vbmi2")
+            && is_x86_feature_detected!("avx512vbmi")
+            && is_x86_feature_detected!("bmi2"))
+        {
+            return;
+        }
+        let dense = [
+            "\"\\\"\\".repeat(50),
+            "\n\t\r\x08\x0c\"\\".repeat(40),
+            // `\u00XX` escapes interrupt the in-register expansion.
+            "a\x01\"\n\x1f".repeat(40),
+            r#"{"a":"b","c":["d\"e"]}"#.repeat(20),
+        ];
+        for input in mixed_inputs().chain(dense) {
+            assert_kernel_escapes(simd::avx512::format_string_vbmi2, &input);
+        }
+    }
+
+    #[allow(dead_code)]
+    #[track_caller]
+    fn assert_kernel_escapes(kernel: unsafe fn(&str, &mut [u8], Quote) -> usize, input: &str) {
+        let mut output = vec![0; input.len() * 6 + 32 + 3];
+        let len = unsafe { kernel(input, &mut output, Quote) };
+        assert_eq!(
+            output[..len],
+            *serde_json::to_string(input).unwrap().as_bytes(),
+            "input: {input:?}"
+        );
+    }
+
+    /// Pseudo-random strings of every length up to 300 mixing clean ASCII,
+    /// escapes, `DEL` and multi-byte UTF-8.
+    fn mixed_inputs() -> impl Iterator<Item = String> {
+        let alphabet: Vec<char> = "ab \"\\\n\t\x00\x1f\x7f中😊".chars().collect();
+        // A fixed LCG keeps failures reproducible.
+        let mut seed: u32 = 0x2545_f491;
+        (0..=300usize).map(move |len| {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    alphabet[(seed >> 16) as usize % alphabet.len()]
+                })
+                .collect()
+        })
+    }
+
     #[test]
     fn test_rxjs() {
         let mut sources = Vec::new();
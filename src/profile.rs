@@ -0,0 +1,224 @@
+//! Escaping that matches other JSON encoders byte for byte.
+//!
+//! The kernels escape what serde_json does. A profile marks the extra bytes
+//! its encoder escapes differently: the text in between still goes through the
+//! kernels, and only those chars take the slow path.
+
+use crate::{
+    Escaper, escape_into, grow,
+    simd::{Quote, nibbles},
+};
+
+/// A JSON encoder whose string escaping to match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// `serde_json`, which is what [`escape`](crate::escape) does.
+    SerdeJson,
+    /// `JSON.stringify`, which escapes valid UTF-8 the same as `serde_json`.
+    Ecma262,
+    /// Go's `json.Marshal` (Go 1.22 and later): also escapes `<`, `>`, `&`,
+    /// U+2028 and U+2029.
+    GoEncodingJson,
+    /// Python's `json.dumps` with the default `ensure_ascii=True`: also
+    /// escapes DEL and all non-ASCII chars, as UTF-16.
+    PythonJson,
+    /// PHP's `json_encode` without flags: also escapes `/` and all non-ASCII
+    /// chars, as UTF-16.
+    PhpJsonEncode,
+    /// Jackson's default `JsonGenerator`: uppercase hex in `\u00XX` escapes.
+    Jackson,
+}
+
+impl Profile {
+    fn table(self) -> Option<&'static Table> {
+        match self {
+            Profile::SerdeJson | Profile::Ecma262 => None,
+            Profile::GoEncodingJson => Some(&GO),
+            Profile::PythonJson => Some(&PYTHON),
+            Profile::PhpJsonEncode => Some(&PHP),
+            Profile::Jackson => Some(&JACKSON),
+        }
+    }
+}
+
+/// What a profile escapes on top of, or unlike, the kernels.
+struct Table {
+    /// Nibble tables of the bytes that may start a char the profile escapes
+    /// differently, for [`nibbles::find`].
+    lo: [u8; 16],
+    hi: [u8; 16],
+    upper_hex: bool,
+    slash: bool,
+    del: bool,
+    html: bool,
+    line_separators: bool,
+    ascii: bool,
+}
+
+impl Table {
+    /// Checks at compile time that the nibble tables classify exactly the
+    /// bytes the flags need.
+    const fn checked(self) -> Self {
+        let mut b = 0;
+        while b < 256 {
+            let c = b as u8;
+            let special = (self.upper_hex
+                && c < 0x20
+                && !matches!(c, 0x08..=0x0a | 0x0c | 0x0d))
+                || (self.slash && c == b'/')
+                || (self.del && c == 0x7f)
+                || (self.html && matches!(c, b'<' | b'>' | b'&'))
+                // The lead byte of U+2028 and U+2029.
+                || (self.line_separators && c == 0xe2)
+                || (self.ascii && c >= 0x80);
+            let class = self.lo[b & 0xf] & self.hi[b >> 4];
+            assert!(
+                special == (class != 0),
+                "nibble tables do not match the flags"
+            );
+            b += 1;
+        }
+        self
+    }
+
+    /// Offset of the first special byte in `bytes`.
+    fn find(&self, bytes: &[u8]) -> Option<usize> {
+        nibbles::find(bytes, &self.lo, &self.hi)
+    }
+
+    /// Writes how the profile escapes `c` to `buf`, or returns `None` if the
+    /// kernels escape it the same way.
+    fn escape(&self, c: char, buf: &mut [u8; 12]) -> Option<usize> {
+        let hex: &[u8; 16] = if self.upper_hex {
+            b"0123456789ABCDEF"
+        } else {
+            b"0123456789abcdef"
+        };
+        // Writes `\uXXXX` at `at` and returns where it ends.
+        let mut unit = |at: usize, u: u16| {
+            buf[at..at + 2].copy_from_slice(b"\\u");
+            for i in 0..4 {
+                buf[at + 2 + i] = hex[(u >> (12 - 4 * i)) as usize & 0xf];
+            }
+            at + 6
+        };
+        match c {
+            '/' if self.slash => {
+                buf[..2].copy_from_slice(b"\\/");
+                Some(2)
+            }
+            '\0'..='\x1f' if self.upper_hex => Some(unit(0, c as u16)),
+            '\x7f' if self.del => Some(unit(0, 0x7f)),
+            '<' | '>' | '&' if self.html => Some(unit(0, c as u16)),
+            '\u{2028}' | '\u{2029}' if self.line_separators => Some(unit(0, c as u16)),
+            _ if self.ascii && !c.is_ascii() => {
+                let mut units = [0; 2];
+                let units = c.encode_utf16(&mut units);
+                Some(units.iter().fold(0, |at, &u| unit(at, u)))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Each special byte is the intersection of a class bit in the table for its
+// low nibble and in the table for its high nibble, as in the SSSE3 kernel.
+//
+//   `<`, `>`   bit 0: low nibbles 0xc and 0xe, high nibble 3
+//   `&`        bit 1: low nibble 6, high nibble 2
+//   0xe2       bit 2: low nibble 2, high nibble 0xe
+static GO: Table = Table {
+    lo: [0, 0, 4, 0, 0, 0, 2, 0, 0, 0, 0, 0, 1, 0, 1, 0],
+    hi: [0, 0, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 0],
+    upper_hex: false,
+    slash: false,
+    del: false,
+    html: true,
+    line_separators: true,
+    ascii: false,
+}
+.checked();
+
+//   0x80..     bit 0: every low nibble, high nibbles 8 to 0xf
+//   DEL        bit 1: low nibble 0xf, high nibble 7
+static PYTHON: Table = Table {
+    lo: [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 3],
+    hi: [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+    upper_hex: false,
+    slash: false,
+    del: true,
+    html: false,
+    line_separators: false,
+    ascii: true,
+}
+.checked();
+
+//   0x80..     bit 0: every low nibble, high nibbles 8 to 0xf
+//   `/`        bit 1: low nibble 0xf, high nibble 2
+static PHP: Table = Table {
+    lo: [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 3],
+    hi: [0, 0, 2, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1],
+    upper_hex: false,
+    slash: true,
+    del: false,
+    html: false,
+    line_separators: false,
+    ascii: true,
+}
+.checked();
+
+// The controls without a short escape:
+//   0x10..=0x1f  bit 0: every low nibble, high nibble 1
+//   0x00..=0x0f  bit 1: low nibbles 0 to 7, 0xb, 0xe and 0xf, high nibble 0
+static JACKSON: Table = Table {
+    lo: [3, 3, 3, 3, 3, 3, 3, 3, 1, 1, 1, 3, 1, 1, 3, 3],
+    hi: [2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    upper_hex: true,
+    slash: false,
+    del: false,
+    html: false,
+    line_separators: false,
+    ascii: false,
+}
+.checked();
+
+/// Like [`escape`](crate::escape), but escapes as `profile`'s encoder does.
+pub fn escape_with_profile(value: &str, profile: Profile) -> String {
+    let mut dst = Vec::new();
+    escape_into_with_profile(value, profile, &mut dst);
+    // SAFETY: the escaped output is valid UTF-8.
+    unsafe { String::from_utf8_unchecked(dst) }
+}
+
+/// Like [`escape_into`], but escapes as `profile`'s encoder does.
+pub fn escape_into_with_profile(value: &str, profile: Profile, dst: &mut Vec<u8>) {
+    let bytes = value.as_bytes();
+    let Some((table, first)) = profile
+        .table()
+        .and_then(|table| Some((table, table.find(bytes)?)))
+    else {
+        escape_into(value, dst);
+        return;
+    };
+
+    let mut escaper = Escaper::new(dst, grow, Quote);
+    let mut buf = [0; 12];
+    let mut at = first;
+    let mut start = 0;
+    loop {
+        // Special bytes start chars, so `start..at` are whole chars.
+        let c = value[at..].chars().next().unwrap();
+        let end = at + c.len_utf8();
+        if let Some(n) = table.escape(c, &mut buf) {
+            let Ok(()) = escaper.push(&value[start..at]);
+            let Ok(()) = escaper.push_escaped(&buf[..n]);
+            start = end;
+        }
+        match table.find(&bytes[end..]) {
+            Some(next) => at = end + next,
+            None => break,
+        }
+    }
+    let Ok(()) = escaper.push(&value[start..]);
+    let Ok(()) = escaper.finish();
+}
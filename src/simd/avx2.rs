@@ -5,7 +5,7 @@ use std::arch::x86_64::*;
 
 use std::ops::{BitAnd, BitOr, BitOrAssign};
 
-use super::{Mask, Simd, traits::BitMask, util::escape_unchecked};
+use super::{Mask, Simd, pshufb::escape_dense, traits::BitMask, util::SlowPath};
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use super::util::check_cross_page;
@@ -104,7 +104,7 @@ fn escaped_mask(v: Simd256u) -> u32 {
 }
 
 #[target_feature(enable = "avx2")]
-pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
+pub unsafe fn format_string<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
     unsafe {
         let slice = value.as_bytes();
         let mut sptr = slice.as_ptr();
@@ -147,7 +147,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -161,7 +161,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -175,7 +175,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -189,7 +189,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -213,7 +213,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                 nb -= cn;
                 dptr = dptr.add(cn);
                 sptr = sptr.add(cn);
-                escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
             }
         }
 
@@ -256,7 +256,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                 nb -= cn;
                 dptr = dptr.add(cn);
                 sptr = sptr.add(cn);
-                escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
             }
         }
 
@@ -5,7 +5,11 @@ use std::arch::x86_64::*;
 
 use std::ops::{BitAnd, BitOr, BitOrAssign};
 
-use super::{Mask, Simd, traits::BitMask, util::escape_unchecked};
+use super::{
+    Mask, Simd,
+    traits::BitMask,
+    util::{SlowPath, escape_unchecked},
+};
 
 const LANES: usize = 64;
 const CHUNK: usize = LANES * 4;
@@ -93,150 +97,273 @@ fn escaped_mask(v: Simd512u) -> u64 {
     v.bitmask()
 }
 
-#[target_feature(enable = "avx512f,avx512bw,avx512vl")]
-pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
+/// Escapes the run of bytes starting at `*src` like [`escape_unchecked`], but
+/// 32 input bytes at a time: `"`, `\` and the control characters with a
+/// two-byte escape are expanded within a vector with `vpexpandb`, and only the
+/// `\u00XX` escapes go through `slow`, as do all control characters unless
+/// [`SlowPath::short_controls`]. Returns at the first block without any
+/// escapes, or once the input is exhausted.
+///
+/// # Safety
+///
+/// Same as [`escape_unchecked`], and the CPU must support AVX-512 VBMI/VBMI2
+/// and BMI2.
+#[cfg(target_arch = "x86_64")]
+#[inline]
+#[target_feature(enable = "avx512f,avx512bw,avx512vl,avx512vbmi,avx512vbmi2,bmi2")]
+unsafe fn escape_expand<P: SlowPath>(
+    src: &mut *const u8,
+    nb: &mut usize,
+    dst: &mut *mut u8,
+    slow: P,
+) {
+    const BLOCK: usize = 32;
+    // The second byte of the two-byte escape of each control character, or 0
+    // if it needs `\u00XX`.
+    const SHORT_ESCAPES: [u8; 32] = {
+        let mut tab = [0; 32];
+        tab[0x08] = b'b';
+        tab[0x09] = b't';
+        tab[0x0a] = b'n';
+        tab[0x0c] = b'f';
+        tab[0x0d] = b'r';
+        tab
+    };
+    // Output slot `2 * i` is the backslash of input byte `i` (if it is
+    // escaped) and slot `2 * i + 1` the byte itself or its escape letter.
+    const BACKSLASH_SLOTS: u64 = 0x5555_5555_5555_5555;
+    const BYTE_SLOTS: u64 = 0xaaaa_aaaa_aaaa_aaaa;
+
     unsafe {
-        let slice = value.as_bytes();
-        let mut sptr = slice.as_ptr();
-        let mut dptr = dst.as_mut_ptr();
-        let dstart = dptr;
-        let mut nb: usize = slice.len();
-
-        *dptr = b'"';
-        dptr = dptr.add(1);
-
-        // Process CHUNK (4 * LANES = 256 bytes) at a time
-        while nb >= CHUNK {
-            // Load 4 SIMD vectors
-            let v1 = Simd512u::loadu(sptr);
-            let v2 = Simd512u::loadu(sptr.add(LANES));
-            let v3 = Simd512u::loadu(sptr.add(LANES * 2));
-            let v4 = Simd512u::loadu(sptr.add(LANES * 3));
-
-            // Check all 4 masks
-            let mask1 = escaped_mask(v1);
-            let mask2 = escaped_mask(v2);
-            let mask3 = escaped_mask(v3);
-            let mask4 = escaped_mask(v4);
-
-            // Fast path: single OR-combined mask test => 1 branch, lets the 4
-            // independent load+mask dependency chains pipeline in parallel.
-            if (mask1 | mask2 | mask3 | mask4) == 0 {
-                v1.storeu(dptr);
-                v2.storeu(dptr.add(LANES));
-                v3.storeu(dptr.add(LANES * 2));
-                v4.storeu(dptr.add(LANES * 3));
-                nb -= CHUNK;
-                dptr = dptr.add(CHUNK);
-                sptr = sptr.add(CHUNK);
-            } else {
-                // Slow path: handle escape character
-                // Process v1
-                v1.storeu(dptr);
-                if !mask1.all_zero() {
-                    let cn = mask1.first_offset();
-                    nb -= cn;
-                    dptr = dptr.add(cn);
-                    sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
-                    continue;
-                }
-                nb -= LANES;
-                dptr = dptr.add(LANES);
-                sptr = sptr.add(LANES);
-
-                // Process v2
-                v2.storeu(dptr);
-                if !mask2.all_zero() {
-                    let cn = mask2.first_offset();
-                    nb -= cn;
-                    dptr = dptr.add(cn);
-                    sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
-                    continue;
-                }
-                nb -= LANES;
-                dptr = dptr.add(LANES);
-                sptr = sptr.add(LANES);
-
-                // Process v3
-                v3.storeu(dptr);
-                if !mask3.all_zero() {
-                    let cn = mask3.first_offset();
-                    nb -= cn;
-                    dptr = dptr.add(cn);
-                    sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
-                    continue;
-                }
-                nb -= LANES;
-                dptr = dptr.add(LANES);
-                sptr = sptr.add(LANES);
-
-                // Process v4
-                v4.storeu(dptr);
-                if !mask4.all_zero() {
-                    let cn = mask4.first_offset();
-                    nb -= cn;
-                    dptr = dptr.add(cn);
-                    sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
-                    continue;
-                }
-                nb -= LANES;
-                dptr = dptr.add(LANES);
-                sptr = sptr.add(LANES);
+        let short_escapes = _mm256_loadu_si256(SHORT_ESCAPES.as_ptr() as *const __m256i);
+        let x1f = _mm256_set1_epi8(0x1f);
+        let quote = _mm256_set1_epi8(b'"' as i8);
+        let blash = _mm256_set1_epi8(b'\\' as i8);
+        loop {
+            let n = (*nb).min(BLOCK);
+            let k = _bzhi_u32(u32::MAX, n as u32);
+            let v = _mm256_maskz_loadu_epi8(k, *src as *const i8);
+            let control = _mm256_cmple_epu8_mask(v, x1f) & k;
+            let escaped =
+                control | _mm256_cmpeq_epi8_mask(v, quote) | _mm256_cmpeq_epi8_mask(v, blash);
+            if escaped == 0 {
+                return;
             }
-        }
-
-        // Process remaining LANES bytes at a time
-        while nb >= LANES {
-            let v = Simd512u::loadu(sptr);
-            v.storeu(dptr);
-            let mask = escaped_mask(v);
 
-            if mask.all_zero() {
-                nb -= LANES;
-                dptr = dptr.add(LANES);
-                sptr = sptr.add(LANES);
+            // `vpermb` only looks at the low five bits, which is exactly the
+            // control character range.
+            let letters = _mm256_permutexvar_epi8(v, short_escapes);
+            let long = if slow.short_controls() {
+                control & _mm256_cmpeq_epi8_mask(letters, _mm256_setzero_si256())
             } else {
-                let cn = mask.first_offset();
-                nb -= cn;
-                dptr = dptr.add(cn);
-                sptr = sptr.add(cn);
-                escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                control
+            };
+            let take = if long == 0 {
+                n
+            } else {
+                long.trailing_zeros() as usize
+            };
+            let lanes = _bzhi_u32(u32::MAX, take as u32);
+            let escaped = escaped & lanes;
+
+            let slots =
+                _pdep_u64(escaped as u64, BACKSLASH_SLOTS) | _pdep_u64(lanes as u64, BYTE_SLOTS);
+            let byte_slots = _pext_u64(BYTE_SLOTS, slots);
+            let out_len = take + escaped.count_ones() as usize;
+            let bytes = _mm256_mask_blend_epi8(control, v, letters);
+            let out = _mm512_mask_mov_epi8(
+                _mm512_maskz_expand_epi8(byte_slots, _mm512_zextsi256_si512(bytes)),
+                !byte_slots,
+                _mm512_set1_epi8(b'\\' as i8),
+            );
+            _mm512_mask_storeu_epi8(*dst as *mut i8, _bzhi_u64(u64::MAX, out_len as u32), out);
+
+            *src = src.add(take);
+            *nb -= take;
+            *dst = dst.add(out_len);
+            if take < n {
+                escape_unchecked(src, nb, dst, slow);
             }
         }
+    }
+}
 
-        // Handle the remaining (< LANES) bytes with an AVX-512BW masked load +
-        // masked store. `nb` is always in 1..=63 here, so k has 1..=63 low bits
-        // set. The zero-masking load `_mm512_maskz_loadu_epi8` suppresses faults
-        // on the masked-off (high) lanes, so it never reads past the valid input
-        // -> page-safe without any placeholder copy. The masked store writes
-        // exactly `nb` bytes, so it also never touches memory beyond the logical
-        // output. The high (masked-off) lanes read as zero, which would look like
-        // escape candidates (0x00 <= 0x1f), so we clear the top LANES-nb mask
-        // bits exactly as the scalar path did.
-        while nb > 0 {
-            let k: __mmask64 = (1u64 << nb) - 1;
-            let v = Simd512u(_mm512_maskz_loadu_epi8(k, sptr as *const i8));
-            _mm512_mask_storeu_epi8(dptr as *mut i8, k, v.0);
-            let mask = escaped_mask(v).clear_high_bits(LANES - nb);
-
-            if mask.all_zero() {
-                dptr = dptr.add(nb);
-                break;
-            } else {
-                let cn = mask.first_offset();
-                nb -= cn;
-                dptr = dptr.add(cn);
-                sptr = sptr.add(cn);
-                escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+macro_rules! impl_format_string {
+    ($name:ident, $features:literal, $escape:ident) => {
+        #[target_feature(enable = $features)]
+        pub(crate) unsafe fn $name<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
+            unsafe {
+                let slice = value.as_bytes();
+                let mut sptr = slice.as_ptr();
+                let mut dptr = dst.as_mut_ptr();
+                let dstart = dptr;
+                let mut nb: usize = slice.len();
+
+                *dptr = b'"';
+                dptr = dptr.add(1);
+
+                // Process CHUNK (4 * LANES = 256 bytes) at a time
+                while nb >= CHUNK {
+                    // Load 4 SIMD vectors
+                    let v1 = Simd512u::loadu(sptr);
+                    let v2 = Simd512u::loadu(sptr.add(LANES));
+                    let v3 = Simd512u::loadu(sptr.add(LANES * 2));
+                    let v4 = Simd512u::loadu(sptr.add(LANES * 3));
+
+                    // Check all 4 masks
+                    let mask1 = escaped_mask(v1);
+                    let mask2 = escaped_mask(v2);
+                    let mask3 = escaped_mask(v3);
+                    let mask4 = escaped_mask(v4);
+
+                    // Fast path: single OR-combined mask test => 1 branch, lets the 4
+                    // independent load+mask dependency chains pipeline in parallel.
+                    if (mask1 | mask2 | mask3 | mask4) == 0 {
+                        v1.storeu(dptr);
+                        v2.storeu(dptr.add(LANES));
+                        v3.storeu(dptr.add(LANES * 2));
+                        v4.storeu(dptr.add(LANES * 3));
+                        nb -= CHUNK;
+                        dptr = dptr.add(CHUNK);
+                        sptr = sptr.add(CHUNK);
+                    } else {
+                        // Slow path: handle escape character
+                        // Process v1
+                        v1.storeu(dptr);
+                        if !mask1.all_zero() {
+                            let cn = mask1.first_offset();
+                            nb -= cn;
+                            dptr = dptr.add(cn);
+                            sptr = sptr.add(cn);
+                            $escape(&mut sptr, &mut nb, &mut dptr, slow);
+                            continue;
+                        }
+                        nb -= LANES;
+                        dptr = dptr.add(LANES);
+                        sptr = sptr.add(LANES);
+
+                        // Process v2
+                        v2.storeu(dptr);
+                        if !mask2.all_zero() {
+                            let cn = mask2.first_offset();
+                            nb -= cn;
+                            dptr = dptr.add(cn);
+                            sptr = sptr.add(cn);
+                            $escape(&mut sptr, &mut nb, &mut dptr, slow);
+                            continue;
+                        }
+                        nb -= LANES;
+                        dptr = dptr.add(LANES);
+                        sptr = sptr.add(LANES);
+
+                        // Process v3
+                        v3.storeu(dptr);
+                        if !mask3.all_zero() {
+                            let cn = mask3.first_offset();
+                            nb -= cn;
+                            dptr = dptr.add(cn);
+                            sptr = sptr.add(cn);
+                            $escape(&mut sptr, &mut nb, &mut dptr, slow);
+                            continue;
+                        }
+                        nb -= LANES;
+                        dptr = dptr.add(LANES);
+                        sptr = sptr.add(LANES);
+
+                        // Process v4
+                        v4.storeu(dptr);
+                        if !mask4.all_zero() {
+                            let cn = mask4.first_offset();
+                            nb -= cn;
+                            dptr = dptr.add(cn);
+                            sptr = sptr.add(cn);
+                            $escape(&mut sptr, &mut nb, &mut dptr, slow);
+                            continue;
+                        }
+                        nb -= LANES;
+                        dptr = dptr.add(LANES);
+                        sptr = sptr.add(LANES);
+                    }
+                }
+
+                // Process remaining LANES bytes at a time
+                while nb >= LANES {
+                    let v = Simd512u::loadu(sptr);
+                    v.storeu(dptr);
+                    let mask = escaped_mask(v);
+
+                    if mask.all_zero() {
+                        nb -= LANES;
+                        dptr = dptr.add(LANES);
+                        sptr = sptr.add(LANES);
+                    } else {
+                        let cn = mask.first_offset();
+                        nb -= cn;
+                        dptr = dptr.add(cn);
+                        sptr = sptr.add(cn);
+                        $escape(&mut sptr, &mut nb, &mut dptr, slow);
+                    }
+                }
+
+                // Handle the remaining (< LANES) bytes with an AVX-512BW masked load +
+                // masked store. `nb` is always in 1..=63 here, so k has 1..=63 low bits
+                // set. The zero-masking load `_mm512_maskz_loadu_epi8` suppresses faults
+                // on the masked-off (high) lanes, so it never reads past the valid input
+                // -> page-safe without any placeholder copy. The masked store writes
+                // exactly `nb` bytes, so it also never touches memory beyond the logical
+                // output. The high (masked-off) lanes read as zero, which would look like
+                // escape candidates (0x00 <= 0x1f), so we clear the top LANES-nb mask
+                // bits exactly as the scalar path did.
+                while nb > 0 {
+                    let k: __mmask64 = (1u64 << nb) - 1;
+                    let v = Simd512u(_mm512_maskz_loadu_epi8(k, sptr as *const i8));
+                    _mm512_mask_storeu_epi8(dptr as *mut i8, k, v.0);
+                    let mask = escaped_mask(v).clear_high_bits(LANES - nb);
+
+                    if mask.all_zero() {
+                        dptr = dptr.add(nb);
+                        break;
+                    } else {
+                        let cn = mask.first_offset();
+                        nb -= cn;
+                        dptr = dptr.add(cn);
+                        sptr = sptr.add(cn);
+                        $escape(&mut sptr, &mut nb, &mut dptr, slow);
+                    }
+                }
+
+                *dptr = b'"';
+                dptr = dptr.add(1);
+                dptr as usize - dstart as usize
             }
         }
+    };
+}
 
-        *dptr = b'"';
-        dptr = dptr.add(1);
-        dptr as usize - dstart as usize
+impl_format_string!(
+    format_string_bw,
+    "avx512f,avx512bw,avx512vl",
+    escape_unchecked
+);
+#[cfg(target_arch = "x86_64")]
+impl_format_string!(
+    format_string_vbmi2,
+    "avx512f,avx512bw,avx512vl,avx512vbmi,avx512vbmi2,bmi2",
+    escape_expand
+);
+
+/// # Safety
+///
+/// The CPU must support AVX-512F/BW/VL.
+#[inline(always)]
+pub unsafe fn format_string<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
+    // Dense escapes are expanded in-register when VBMI2 is available.
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx512vbmi2")
+        && is_x86_feature_detected!("avx512vbmi")
+        && is_x86_feature_detected!("bmi2")
+    {
+        return unsafe { format_string_vbmi2(value, dst, slow) };
     }
+    unsafe { format_string_bw(value, dst, slow) }
 }
@@ -1,16 +1,97 @@
 #![allow(non_camel_case_types)]
+// Only the fallback kernel is called with `--cfg json_escape_simd_fallback`.
+#![cfg_attr(json_escape_simd_fallback, allow(dead_code, unused_imports))]
 
-#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+#[cfg(all(
+    any(target_arch = "x86_64", target_arch = "x86"),
+    not(all(target_feature = "avx512bw", target_feature = "avx512vl"))
+))]
 pub(crate) mod avx2;
-#[cfg(all(any(target_arch = "x86_64", target_arch = "x86"), feature = "avx512"))]
+#[cfg(all(
+    any(target_arch = "x86_64", target_arch = "x86"),
+    any(
+        feature = "avx512",
+        all(target_feature = "avx512bw", target_feature = "avx512vl")
+    )
+))]
 pub(crate) mod avx512;
 pub mod bits;
+// The kernel for CPUs without any of the hand-written SIMD extensions, which
+// is unreachable once one of them is statically guaranteed by the target,
+// unless `--cfg json_escape_simd_fallback` forces it.
+#[cfg(any(
+    json_escape_simd_fallback,
+    not(any(
+        all(target_arch = "aarch64", target_feature = "neon"),
+        all(target_arch = "riscv64", target_feature = "v"),
+        all(target_arch = "wasm32", target_feature = "simd128"),
+        all(
+            any(target_arch = "x86_64", target_arch = "x86"),
+            target_feature = "avx2"
+        )
+    ))
+))]
+pub(crate) mod fallback {
+    #[cfg(feature = "portable-simd")]
+    pub(crate) use super::portable::format_string;
+    #[cfg(not(feature = "portable-simd"))]
+    pub(crate) use super::v128::format_string;
+}
+#[cfg(all(target_arch = "loongarch64", feature = "nightly"))]
+pub(crate) mod lasx;
+#[cfg(all(target_arch = "loongarch64", feature = "nightly"))]
+pub(crate) mod lsx;
 #[cfg(target_arch = "aarch64")]
 pub(crate) mod neon;
-#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+pub(crate) mod nibbles;
+// Also the reference the hand-written kernels are tested against, so it is
+// compiled even when one of them is statically guaranteed by the target.
+#[cfg(feature = "portable-simd")]
+#[allow(dead_code)]
+pub(crate) mod portable;
+#[cfg(all(
+    any(target_arch = "x86_64", target_arch = "x86"),
+    not(all(target_feature = "avx512bw", target_feature = "avx512vl"))
+))]
+mod pshufb;
+#[cfg(target_arch = "riscv64")]
+pub(crate) mod rvv;
+#[cfg(all(target_arch = "s390x", feature = "nightly"))]
+pub(crate) mod s390x_vector;
+#[cfg(all(
+    any(target_arch = "x86_64", target_arch = "x86"),
+    not(target_feature = "avx2")
+))]
 pub(crate) mod sse2;
+#[cfg(all(
+    any(target_arch = "x86_64", target_arch = "x86"),
+    not(target_feature = "avx2")
+))]
+pub(crate) mod ssse3;
+#[cfg(target_arch = "aarch64")]
+pub(crate) mod sve;
 mod traits;
 mod util;
+#[cfg(all(
+    not(feature = "portable-simd"),
+    any(
+        json_escape_simd_fallback,
+        not(any(
+            all(target_arch = "aarch64", target_feature = "neon"),
+            all(target_arch = "riscv64", target_feature = "v"),
+            all(target_arch = "wasm32", target_feature = "simd128"),
+            all(
+                any(target_arch = "x86_64", target_arch = "x86"),
+                target_feature = "avx2"
+            )
+        ))
+    )
+))]
 pub(crate) mod v128;
+#[cfg(all(target_arch = "powerpc64", feature = "nightly"))]
+pub(crate) mod vsx;
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+pub(crate) mod wasm32;
 
 pub use self::traits::{Mask, Simd};
+pub(crate) use self::util::{Quote, SlowPath};
@@ -1,6 +1,12 @@
 use std::arch::aarch64::*;
 
-use super::{Mask, Simd, bits::NeonBits, traits::BitMask, util::escape_unchecked};
+use super::{
+    Mask, Simd,
+    bits::NeonBits,
+    traits::BitMask,
+    util::{DENSE_ESCAPES, EXPAND_SHUFFLE, SHORT_ESCAPES, SlowPath, escape_unchecked},
+};
+use crate::NEED_ESCAPED;
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use super::util::check_cross_page;
@@ -110,8 +116,65 @@ fn escaped_mask(v: Simd128u) -> NeonBits {
     escaped_mask_vec(v).bitmask()
 }
 
+/// Escapes the run of bytes starting at `*src` like [`escape_unchecked`], but
+/// expands whole 16-byte blocks with `tbl` while they hold at least
+/// [`DENSE_ESCAPES`] escapes and none of them needs `\u00XX`, or is a control
+/// character at all unless [`SlowPath::short_controls`].
+#[inline(always)]
+unsafe fn escape_dense<P: SlowPath>(
+    src: &mut *const u8,
+    nb: &mut usize,
+    dst: &mut *mut u8,
+    slow: P,
+) {
+    const BIT_WEIGHTS: [u8; 16] = [1, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128];
+
+    unsafe {
+        let short_escapes = vld1q_u8(SHORT_ESCAPES.as_ptr());
+        let bit_weights = vld1q_u8(BIT_WEIGHTS.as_ptr());
+        let x1f = vdupq_n_u8(0x1f);
+        let blash = vdupq_n_u8(b'\\');
+        let quote = vdupq_n_u8(b'"');
+        while *nb >= 16 {
+            let v = vld1q_u8(*src);
+            let control = vcleq_u8(v, x1f);
+            let escaped = vorrq_u8(control, vorrq_u8(vceqq_u8(v, blash), vceqq_u8(v, quote)));
+            // Out-of-range `tbl` indices read as 0, so only 0x00..=0x0f can get
+            // a letter.
+            let letters = vqtbl1q_u8(short_escapes, v);
+            let long = if slow.short_controls() {
+                vandq_u8(control, vceqzq_u8(letters))
+            } else {
+                control
+            };
+            // Per-half bitmasks, lane `i` -> bit `i % 8`.
+            let bits = vandq_u8(escaped, bit_weights);
+            let lo = vaddv_u8(vget_low_u8(bits)) as u32;
+            let hi = vaddv_u8(vget_high_u8(bits)) as u32;
+            if vmaxvq_u8(long) != 0 || lo.count_ones() + hi.count_ones() < DENSE_ESCAPES {
+                break;
+            }
+
+            let bytes = vbslq_u8(control, letters, v);
+            for (half, mask) in [
+                (vcombine_u8(vget_low_u8(bytes), vget_low_u8(blash)), lo),
+                (vcombine_u8(vget_high_u8(bytes), vget_low_u8(blash)), hi),
+            ] {
+                let shuffle = vld1q_u8(EXPAND_SHUFFLE[mask as usize].as_ptr());
+                vst1q_u8(*dst, vqtbl1q_u8(half, shuffle));
+                *dst = dst.add(8 + mask.count_ones() as usize);
+            }
+            *src = src.add(16);
+            *nb -= 16;
+        }
+        if *nb > 0 && NEED_ESCAPED[**src as usize] != 0 {
+            escape_unchecked(src, nb, dst, slow);
+        }
+    }
+}
+
 #[target_feature(enable = "neon")]
-pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
+pub unsafe fn format_string<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
     unsafe {
         let slice = value.as_bytes();
         let mut sptr = slice.as_ptr();
@@ -154,7 +217,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -168,7 +231,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -182,7 +245,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -196,7 +259,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -220,7 +283,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                 nb -= cn;
                 dptr = dptr.add(cn);
                 sptr = sptr.add(cn);
-                escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
             }
         }
 
@@ -263,7 +326,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                 nb -= cn;
                 dptr = dptr.add(cn);
                 sptr = sptr.add(cn);
-                escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                escape_dense(&mut sptr, &mut nb, &mut dptr, slow);
             }
         }
 
@@ -0,0 +1,107 @@
+//! Byte classification with two 16-entry tables, indexed by the low and the
+//! high nibble of each byte: a byte is in the set when the two entries share a
+//! class bit. With one bit per group of bytes that share a row or a column,
+//! this covers any set of up to eight such groups in two `pshufb` (or `tbl`)
+//! lookups.
+//!
+//! The SSSE3 kernel classifies the escape set this way, and the escape
+//! profiles their extra bytes.
+
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Class bits of each byte, `lo[b & 0xf] & hi[b >> 4]`, gathered as a bitmask
+/// of the lanes with any of them set.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[inline(always)]
+pub(crate) fn classify(v: __m128i, lo: __m128i, hi: __m128i) -> u16 {
+    unsafe {
+        let nibble = _mm_set1_epi8(0x0f);
+        let lo_nibbles = _mm_and_si128(v, nibble);
+        let hi_nibbles = _mm_and_si128(_mm_srli_epi16(v, 4), nibble);
+        let class = _mm_and_si128(
+            _mm_shuffle_epi8(lo, lo_nibbles),
+            _mm_shuffle_epi8(hi, hi_nibbles),
+        );
+        !(_mm_movemask_epi8(_mm_cmpeq_epi8(class, _mm_setzero_si128())) as u16)
+    }
+}
+
+/// Offset of the first byte of `bytes` in the set the tables describe.
+#[inline]
+pub(crate) fn find(bytes: &[u8], lo: &[u8; 16], hi: &[u8; 16]) -> Option<usize> {
+    #[cfg(all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        not(json_escape_simd_fallback)
+    ))]
+    {
+        if is_x86_feature_detected!("ssse3") {
+            return unsafe { find_ssse3(bytes, lo, hi) };
+        }
+    }
+    #[cfg(all(target_arch = "aarch64", not(json_escape_simd_fallback)))]
+    {
+        if cfg!(target_os = "macos") || std::arch::is_aarch64_feature_detected!("neon") {
+            return unsafe { find_neon(bytes, lo, hi) };
+        }
+    }
+    find_scalar(bytes, lo, hi)
+}
+
+fn find_scalar(bytes: &[u8], lo: &[u8; 16], hi: &[u8; 16]) -> Option<usize> {
+    bytes
+        .iter()
+        .position(|&b| lo[(b & 0xf) as usize] & hi[(b >> 4) as usize] != 0)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "ssse3")]
+unsafe fn find_ssse3(bytes: &[u8], lo: &[u8; 16], hi: &[u8; 16]) -> Option<usize> {
+    const LANES: usize = 16;
+    unsafe {
+        let lo_tab = _mm_loadu_si128(lo.as_ptr() as *const __m128i);
+        let hi_tab = _mm_loadu_si128(hi.as_ptr() as *const __m128i);
+        let mut at = 0;
+        while at + LANES <= bytes.len() {
+            let v = _mm_loadu_si128(bytes.as_ptr().add(at) as *const __m128i);
+            let mask = classify(v, lo_tab, hi_tab);
+            if mask != 0 {
+                return Some(at + mask.trailing_zeros() as usize);
+            }
+            at += LANES;
+        }
+        find_scalar(&bytes[at..], lo, hi).map(|i| at + i)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn find_neon(bytes: &[u8], lo: &[u8; 16], hi: &[u8; 16]) -> Option<usize> {
+    use super::{Mask, bits::NeonBits, neon::Mask128, traits::BitMask};
+
+    const LANES: usize = 16;
+    unsafe {
+        let lo_tab = vld1q_u8(lo.as_ptr());
+        let hi_tab = vld1q_u8(hi.as_ptr());
+        let nibble = vdupq_n_u8(0x0f);
+        let mut at = 0;
+        while at + LANES <= bytes.len() {
+            let v = vld1q_u8(bytes.as_ptr().add(at));
+            let class = vandq_u8(
+                vqtbl1q_u8(lo_tab, vandq_u8(v, nibble)),
+                vqtbl1q_u8(hi_tab, vshrq_n_u8::<4>(v)),
+            );
+            // Skip the bitmask extraction while the block is clean.
+            if vmaxvq_u8(class) != 0 {
+                let mask: NeonBits = Mask128(vtstq_u8(class, class)).bitmask();
+                return Some(at + mask.first_offset());
+            }
+            at += LANES;
+        }
+        find_scalar(&bytes[at..], lo, hi).map(|i| at + i)
+    }
+}
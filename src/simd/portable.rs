@@ -0,0 +1,215 @@
+//! `std::simd` kernel.
+//!
+//! LLVM lowers the portable vectors to whatever the target offers (or to
+//! scalar code), so this gives vectorized escaping everywhere and serves as
+//! the reference the hand-written kernels are tested against. The main loop
+//! steps down from 64 to 32 to 16 bytes before the tail.
+
+use std::ops::{BitAnd, BitOr, BitOrAssign};
+use std::simd::cmp::{SimdPartialEq, SimdPartialOrd};
+use std::simd::{Mask as SimdMask, u8x16, u8x32, u8x64};
+
+use super::{
+    Mask, Simd,
+    traits::BitMask,
+    util::{SlowPath, escape_unchecked},
+};
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use super::util::check_cross_page;
+
+macro_rules! impl_portable {
+    ($simd:ident, $mask:ident, $vec:ty, $lanes:literal, $bits:ty) => {
+        #[derive(Debug, Clone, Copy)]
+        #[repr(transparent)]
+        pub struct $simd($vec);
+
+        #[derive(Debug, Clone, Copy)]
+        #[repr(transparent)]
+        pub struct $mask(SimdMask<i8, $lanes>);
+
+        impl Mask for $mask {
+            type BitMask = $bits;
+            type Element = u8;
+
+            #[inline(always)]
+            fn bitmask(self) -> Self::BitMask {
+                // `to_bitmask` maps lane `i` to bit `i` on every target.
+                self.0.to_bitmask() as $bits
+            }
+        }
+
+        impl BitAnd<$mask> for $mask {
+            type Output = Self;
+
+            #[inline(always)]
+            fn bitand(self, rhs: $mask) -> Self::Output {
+                $mask(self.0 & rhs.0)
+            }
+        }
+
+        impl BitOr<$mask> for $mask {
+            type Output = Self;
+
+            #[inline(always)]
+            fn bitor(self, rhs: $mask) -> Self::Output {
+                $mask(self.0 | rhs.0)
+            }
+        }
+
+        impl BitOrAssign<$mask> for $mask {
+            #[inline(always)]
+            fn bitor_assign(&mut self, rhs: $mask) {
+                self.0 |= rhs.0;
+            }
+        }
+
+        impl Simd for $simd {
+            const LANES: usize = $lanes;
+            type Mask = $mask;
+            type Element = u8;
+
+            #[inline(always)]
+            unsafe fn loadu(ptr: *const u8) -> Self {
+                $simd(<$vec>::from_array(unsafe {
+                    ptr.cast::<[u8; $lanes]>().read_unaligned()
+                }))
+            }
+
+            #[inline(always)]
+            unsafe fn storeu(&self, ptr: *mut u8) {
+                unsafe {
+                    ptr.cast::<[u8; $lanes]>()
+                        .write_unaligned(self.0.to_array())
+                }
+            }
+
+            #[inline(always)]
+            fn eq(&self, rhs: &Self) -> Self::Mask {
+                $mask(self.0.simd_eq(rhs.0))
+            }
+
+            #[inline(always)]
+            fn splat(ch: u8) -> Self {
+                $simd(<$vec>::splat(ch))
+            }
+
+            #[inline(always)]
+            fn le(&self, rhs: &Self) -> Self::Mask {
+                $mask(self.0.simd_le(rhs.0))
+            }
+        }
+    };
+}
+
+impl_portable!(Simd128u, Mask128, u8x16, 16, u16);
+impl_portable!(Simd256u, Mask256, u8x32, 32, u32);
+impl_portable!(Simd512u, Mask512, u8x64, 64, u64);
+
+#[inline(always)]
+fn escaped_mask<V: Simd<Element = u8>>(v: V) -> <V::Mask as Mask>::BitMask {
+    let x1f = V::splat(0x1f); // 0x00 ~ 0x20
+    let blash = V::splat(b'\\');
+    let quote = V::splat(b'"');
+    // `Mask` only promises `BitOr<Output = Self>` through `BitOrAssign`.
+    let mut mask = v.le(&x1f);
+    mask |= v.eq(&blash);
+    mask |= v.eq(&quote);
+    mask.bitmask()
+}
+
+/// Copies full `V::LANES`-byte vectors while at least that many bytes are left,
+/// escaping as it goes.
+#[inline(always)]
+unsafe fn format_lanes<V: Simd<Element = u8>, P: SlowPath>(
+    sptr: &mut *const u8,
+    nb: &mut usize,
+    dptr: &mut *mut u8,
+    slow: P,
+) {
+    unsafe {
+        while *nb >= V::LANES {
+            let v = V::loadu(*sptr);
+            v.storeu(*dptr);
+            let mask = escaped_mask(v);
+
+            if mask.all_zero() {
+                *nb -= V::LANES;
+                *dptr = dptr.add(V::LANES);
+                *sptr = sptr.add(V::LANES);
+            } else {
+                let cn = mask.first_offset();
+                *nb -= cn;
+                *dptr = dptr.add(cn);
+                *sptr = sptr.add(cn);
+                escape_unchecked(sptr, nb, dptr, slow);
+            }
+        }
+    }
+}
+
+pub fn format_string<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
+    const LANES: usize = 16;
+
+    unsafe {
+        let slice = value.as_bytes();
+        let mut sptr = slice.as_ptr();
+        let mut dptr = dst.as_mut_ptr();
+        let dstart = dptr;
+        let mut nb: usize = slice.len();
+
+        *dptr = b'"';
+        dptr = dptr.add(1);
+
+        format_lanes::<Simd512u, P>(&mut sptr, &mut nb, &mut dptr, slow);
+        format_lanes::<Simd256u, P>(&mut sptr, &mut nb, &mut dptr, slow);
+        format_lanes::<Simd128u, P>(&mut sptr, &mut nb, &mut dptr, slow);
+
+        // Handle remaining bytes
+        let mut placeholder: [u8; LANES] = [0; LANES];
+        while nb > 0 {
+            let v = {
+                #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+                {
+                    std::ptr::copy_nonoverlapping(sptr, placeholder[..].as_mut_ptr(), nb);
+                    Simd128u::loadu(placeholder[..].as_ptr())
+                }
+                #[cfg(any(target_os = "linux", target_os = "macos"))]
+                {
+                    if check_cross_page(sptr, LANES) {
+                        std::ptr::copy_nonoverlapping(sptr, placeholder[..].as_mut_ptr(), nb);
+                        Simd128u::loadu(placeholder[..].as_ptr())
+                    } else {
+                        #[cfg(any(debug_assertions, miri, feature = "asan"))]
+                        {
+                            std::ptr::copy_nonoverlapping(sptr, placeholder[..].as_mut_ptr(), nb);
+                            Simd128u::loadu(placeholder[..].as_ptr())
+                        }
+                        #[cfg(not(any(debug_assertions, miri)))]
+                        {
+                            Simd128u::loadu(sptr)
+                        }
+                    }
+                }
+            };
+
+            v.storeu(dptr);
+            let mask = escaped_mask(v).clear_high_bits(LANES - nb);
+
+            if mask.all_zero() {
+                dptr = dptr.add(nb);
+                break;
+            } else {
+                let cn = mask.first_offset();
+                nb -= cn;
+                dptr = dptr.add(cn);
+                sptr = sptr.add(cn);
+                escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+            }
+        }
+
+        *dptr = b'"';
+        dptr = dptr.add(1);
+        dptr as usize - dstart as usize
+    }
+}
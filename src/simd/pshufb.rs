@@ -0,0 +1,90 @@
+//! Dense escape expansion with `pshufb`, shared by the SSSE3 and AVX2 kernels.
+//!
+//! Text that is itself JSON (logged payloads, embedded documents) escapes a
+//! quote every few bytes, which sends the main loops to [`escape_unchecked`]
+//! for almost every byte. [`escape_dense`] instead expands whole 16-byte
+//! blocks with [`EXPAND_SHUFFLE`] as long as they stay dense.
+//!
+//! A run-length [`escape_unchecked`], writing each run of quotes and
+//! backslashes two bytes per step, was tried instead and dropped. Medians of
+//! three runs of the AVX2 kernel on the `benches/escape.rs` corpora:
+//!
+//! | corpus        | this expansion | run-length only | byte loop only |
+//! |---------------|----------------|-----------------|----------------|
+//! | json in json  | 30.0 µs        | 82.6 µs         | 80.4 µs        |
+//! | dense escapes | 11.7 µs        | 79.1 µs         | 74.4 µs        |
+//!
+//! With the run-length loop behind the expansion too, it was 30.7 µs and
+//! 14.5 µs. JSON-in-JSON rarely repeats a quote or backslash back to back:
+//! its escapes are dense but not runs.
+
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use super::util::{DENSE_ESCAPES, EXPAND_SHUFFLE, SHORT_ESCAPES, SlowPath, escape_unchecked};
+use crate::NEED_ESCAPED;
+
+/// Escapes the run of bytes starting at `*src` like [`escape_unchecked`], but
+/// expands whole 16-byte blocks with `pshufb` while they hold at least
+/// [`DENSE_ESCAPES`] escapes and none of them needs `\u00XX`, or is a control
+/// character at all unless [`SlowPath::short_controls`].
+///
+/// Only needs SSSE3; the caller must have it enabled.
+#[inline(always)]
+pub(crate) unsafe fn escape_dense<P: SlowPath>(
+    src: &mut *const u8,
+    nb: &mut usize,
+    dst: &mut *mut u8,
+    slow: P,
+) {
+    unsafe {
+        let short_escapes = _mm_loadu_si128(SHORT_ESCAPES.as_ptr() as *const __m128i);
+        let x0f = _mm_set1_epi8(0x0f);
+        let x1f = _mm_set1_epi8(0x1f);
+        let blash = _mm_set1_epi8(b'\\' as i8);
+        let quote = _mm_set1_epi8(b'"' as i8);
+        while *nb >= 16 {
+            let v = _mm_loadu_si128(*src as *const __m128i);
+            let control = _mm_cmpeq_epi8(_mm_max_epu8(v, x1f), x1f);
+            let escaped = _mm_or_si128(
+                control,
+                _mm_or_si128(_mm_cmpeq_epi8(v, blash), _mm_cmpeq_epi8(v, quote)),
+            );
+            // `pshufb` only looks at the low nibble, so drop the letters of
+            // 0x10..=0x1f.
+            let low = _mm_cmpeq_epi8(_mm_max_epu8(v, x0f), x0f);
+            let letters = _mm_and_si128(_mm_shuffle_epi8(short_escapes, v), low);
+            let long = if slow.short_controls() {
+                _mm_and_si128(control, _mm_cmpeq_epi8(letters, _mm_setzero_si128()))
+            } else {
+                control
+            };
+            let mask = _mm_movemask_epi8(escaped) as u32;
+            if _mm_movemask_epi8(long) != 0 || mask.count_ones() < DENSE_ESCAPES {
+                break;
+            }
+
+            // A blend without SSE4.1's `pblendvb`.
+            let bytes = _mm_or_si128(
+                _mm_and_si128(control, letters),
+                _mm_andnot_si128(control, v),
+            );
+            for (half, mask) in [
+                (_mm_unpacklo_epi64(bytes, blash), mask & 0xff),
+                (_mm_unpackhi_epi64(bytes, blash), mask >> 8),
+            ] {
+                let shuffle =
+                    _mm_loadu_si128(EXPAND_SHUFFLE[mask as usize].as_ptr() as *const __m128i);
+                _mm_storeu_si128(*dst as *mut __m128i, _mm_shuffle_epi8(half, shuffle));
+                *dst = dst.add(8 + mask.count_ones() as usize);
+            }
+            *src = src.add(16);
+            *nb -= 16;
+        }
+        if *nb > 0 && NEED_ESCAPED[**src as usize] != 0 {
+            escape_unchecked(src, nb, dst, slow);
+        }
+    }
+}
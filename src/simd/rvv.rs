@@ -0,0 +1,139 @@
+//! RISC-V Vector (RVV 1.0) kernel.
+//!
+//! Unlike the fixed-width kernels this one is vector-length agnostic, so it does
+//! not go through the `Simd`/`Mask` traits: every iteration asks `vsetvli` for as
+//! many bytes as the hardware can handle (up to `nb`), which also means the tail
+//! is just a shorter vector and never reads past the input.
+
+use std::arch::asm;
+
+use super::util::{SlowPath, escape_unchecked};
+
+/// Whether the `v` extension can be used on this hart.
+#[inline]
+pub(crate) fn is_available() -> bool {
+    if cfg!(target_feature = "v") {
+        return true;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        use std::sync::atomic::{AtomicU8, Ordering};
+
+        // 0 = not probed yet, 1 = unavailable, 2 = available.
+        static DETECTED: AtomicU8 = AtomicU8::new(0);
+        match DETECTED.load(Ordering::Relaxed) {
+            0 => {
+                let available = linux_hwcap_has_v();
+                DETECTED.store(if available { 2 } else { 1 }, Ordering::Relaxed);
+                available
+            }
+            state => state == 2,
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        false
+    }
+}
+
+// `is_riscv_feature_detected!` is still unstable, so read the single-letter
+// extension bits the kernel exposes in `AT_HWCAP` directly.
+#[cfg(target_os = "linux")]
+fn linux_hwcap_has_v() -> bool {
+    const AT_HWCAP: std::ffi::c_ulong = 16;
+    const HWCAP_ISA_V: std::ffi::c_ulong = 1 << (b'v' - b'a');
+
+    unsafe extern "C" {
+        fn getauxval(ty: std::ffi::c_ulong) -> std::ffi::c_ulong;
+    }
+
+    unsafe { getauxval(AT_HWCAP) & HWCAP_ISA_V != 0 }
+}
+
+/// Copies up to `nb` bytes from `src` to `dst` in a single strip-mined vector
+/// and returns how many bytes were processed together with the offset of the
+/// first byte that needs escaping (`-1` if none).
+///
+/// # Safety
+///
+/// `src` must be valid for `nb` reads and `dst` for `nb` writes, and the hart
+/// must support the `v` extension.
+#[inline(always)]
+unsafe fn copy_until_escape(src: *const u8, dst: *mut u8, nb: usize) -> (usize, isize) {
+    let vl: usize;
+    let first: isize;
+    unsafe {
+        asm!(
+            ".option push",
+            ".option arch, +v",
+            // LMUL=8 groups v8..v15 into one register so each pass covers
+            // 8 * VLEN bits of input.
+            "vsetvli {vl}, {nb}, e8, m8, ta, ma",
+            "vle8.v v8, ({src})",
+            "vmsleu.vx v0, v8, {x1f}",
+            "vmseq.vx v16, v8, {quote}",
+            "vmseq.vx v17, v8, {bslash}",
+            "vmor.mm v0, v0, v16",
+            "vmor.mm v0, v0, v17",
+            "vse8.v v8, ({dst})",
+            "vfirst.m {first}, v0",
+            ".option pop",
+            vl = out(reg) vl,
+            first = out(reg) first,
+            nb = in(reg) nb,
+            src = in(reg) src,
+            dst = in(reg) dst,
+            x1f = in(reg) 0x1f_usize,
+            quote = in(reg) b'"' as usize,
+            bslash = in(reg) b'\\' as usize,
+            out("v0") _,
+            out("v8") _,
+            out("v9") _,
+            out("v10") _,
+            out("v11") _,
+            out("v12") _,
+            out("v13") _,
+            out("v14") _,
+            out("v15") _,
+            out("v16") _,
+            out("v17") _,
+            options(nostack),
+        );
+    }
+    (vl, first)
+}
+
+/// # Safety
+///
+/// The caller must have checked [`is_available`].
+pub unsafe fn format_string<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
+    unsafe {
+        let slice = value.as_bytes();
+        let mut sptr = slice.as_ptr();
+        let mut dptr = dst.as_mut_ptr();
+        let dstart = dptr;
+        let mut nb: usize = slice.len();
+
+        *dptr = b'"';
+        dptr = dptr.add(1);
+
+        while nb > 0 {
+            let (vl, first) = copy_until_escape(sptr, dptr, nb);
+            if first < 0 {
+                nb -= vl;
+                dptr = dptr.add(vl);
+                sptr = sptr.add(vl);
+            } else {
+                let cn = first as usize;
+                nb -= cn;
+                dptr = dptr.add(cn);
+                sptr = sptr.add(cn);
+                escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+            }
+        }
+
+        *dptr = b'"';
+        dptr = dptr.add(1);
+        dptr as usize - dstart as usize
+    }
+}
@@ -0,0 +1,321 @@
+use std::arch::s390x::*;
+
+use std::mem::transmute;
+use std::ops::{BitAnd, BitOr, BitOrAssign};
+
+use super::{
+    Mask, Simd,
+    traits::BitMask,
+    util::movemask,
+    util::{SlowPath, escape_unchecked},
+};
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use super::util::check_cross_page;
+
+const LANES: usize = 16;
+const CHUNK: usize = LANES * 4;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+pub struct Simd128u(vector_unsigned_char);
+
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+pub struct Mask128(vector_unsigned_char);
+
+impl Mask for Mask128 {
+    type BitMask = u16;
+    type Element = u8;
+
+    #[inline(always)]
+    fn bitmask(self) -> Self::BitMask {
+        // Go through memory so that lane `i` is byte `i` on both endiannesses.
+        movemask(unsafe { transmute::<vector_unsigned_char, [u8; 16]>(self.0) })
+    }
+}
+
+impl Mask128 {
+    /// Like [`Mask::bitmask`], with the z14 vector-enhancements-1 `vbperm`
+    /// gathering the bits instead.
+    #[inline(always)]
+    fn bitmask_bperm(self) -> u16 {
+        // `vbperm` puts bit `idx[i]` of the mask, counting from the most
+        // significant, into bit `15 - i` of the first doubleword, so lane `i`
+        // lands in bit `i`.
+        const IDX: [u8; 16] = [
+            120, 112, 104, 96, 88, 80, 72, 64, 56, 48, 40, 32, 24, 16, 8, 0,
+        ];
+        unsafe {
+            let idx = vec_xl(0, IDX.as_ptr());
+            vec_extract(vec_bperm_u128(self.0, idx), 0) as u16
+        }
+    }
+}
+
+impl BitAnd<Mask128> for Mask128 {
+    type Output = Self;
+
+    #[inline(always)]
+    fn bitand(self, rhs: Mask128) -> Self::Output {
+        unsafe { Mask128(vec_and(self.0, rhs.0)) }
+    }
+}
+
+impl BitOr<Mask128> for Mask128 {
+    type Output = Self;
+
+    #[inline(always)]
+    fn bitor(self, rhs: Mask128) -> Self::Output {
+        unsafe { Mask128(vec_or(self.0, rhs.0)) }
+    }
+}
+
+impl BitOrAssign<Mask128> for Mask128 {
+    #[inline(always)]
+    fn bitor_assign(&mut self, rhs: Mask128) {
+        self.0 = unsafe { vec_or(self.0, rhs.0) };
+    }
+}
+
+impl Simd for Simd128u {
+    const LANES: usize = LANES;
+    type Mask = Mask128;
+    type Element = u8;
+
+    #[inline(always)]
+    unsafe fn loadu(ptr: *const u8) -> Self {
+        unsafe { Simd128u(vec_xl(0, ptr)) }
+    }
+
+    #[inline(always)]
+    unsafe fn storeu(&self, ptr: *mut u8) {
+        unsafe { vec_xst(self.0, 0, ptr) }
+    }
+
+    #[inline(always)]
+    fn eq(&self, rhs: &Self) -> Self::Mask {
+        unsafe {
+            Mask128(transmute::<vector_bool_char, vector_unsigned_char>(
+                vec_cmpeq(self.0, rhs.0),
+            ))
+        }
+    }
+
+    #[inline(always)]
+    fn splat(ch: u8) -> Self {
+        unsafe { Simd128u(vec_splats(ch)) }
+    }
+
+    #[inline(always)]
+    fn le(&self, rhs: &Self) -> Self::Mask {
+        unsafe {
+            Mask128(transmute::<vector_bool_char, vector_unsigned_char>(
+                vec_cmple(self.0, rhs.0),
+            ))
+        }
+    }
+}
+
+#[inline(always)]
+fn escaped_mask_vec(v: Simd128u) -> Mask128 {
+    let x1f = Simd128u::splat(0x1f); // 0x00 ~ 0x20
+    let blash = Simd128u::splat(b'\\');
+    let quote = Simd128u::splat(b'"');
+    v.le(&x1f) | v.eq(&blash) | v.eq(&quote)
+}
+
+#[inline(always)]
+fn escaped_mask(v: Simd128u) -> u16 {
+    escaped_mask_vec(v).bitmask()
+}
+
+#[inline(always)]
+fn escaped_mask_bperm(v: Simd128u) -> u16 {
+    escaped_mask_vec(v).bitmask_bperm()
+}
+
+macro_rules! impl_format_string {
+    ($name:ident, $feature:literal, $escaped_mask:ident) => {
+        #[target_feature(enable = $feature)]
+        pub unsafe fn $name<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
+            unsafe {
+                let slice = value.as_bytes();
+                let mut sptr = slice.as_ptr();
+                let mut dptr = dst.as_mut_ptr();
+                let dstart = dptr;
+                let mut nb: usize = slice.len();
+
+                *dptr = b'"';
+                dptr = dptr.add(1);
+
+                // Process CHUNK (4 * LANES = 64 bytes) at a time
+                while nb >= CHUNK {
+                    // Load 4 SIMD vectors
+                    let v1 = Simd128u::loadu(sptr);
+                    let v2 = Simd128u::loadu(sptr.add(LANES));
+                    let v3 = Simd128u::loadu(sptr.add(LANES * 2));
+                    let v4 = Simd128u::loadu(sptr.add(LANES * 3));
+
+                    // Check all 4 masks
+                    let mask1 = $escaped_mask(v1);
+                    let mask2 = $escaped_mask(v2);
+                    let mask3 = $escaped_mask(v3);
+                    let mask4 = $escaped_mask(v4);
+
+                    // Fast path: if all vectors are clean, write the entire chunk
+                    if mask1.all_zero() && mask2.all_zero() && mask3.all_zero() && mask4.all_zero()
+                    {
+                        v1.storeu(dptr);
+                        v2.storeu(dptr.add(LANES));
+                        v3.storeu(dptr.add(LANES * 2));
+                        v4.storeu(dptr.add(LANES * 3));
+                        nb -= CHUNK;
+                        dptr = dptr.add(CHUNK);
+                        sptr = sptr.add(CHUNK);
+                    } else {
+                        // Slow path: handle escape character
+                        // Process v1
+                        v1.storeu(dptr);
+                        if !mask1.all_zero() {
+                            let cn = mask1.first_offset();
+                            nb -= cn;
+                            dptr = dptr.add(cn);
+                            sptr = sptr.add(cn);
+                            escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+                            continue;
+                        }
+                        nb -= LANES;
+                        dptr = dptr.add(LANES);
+                        sptr = sptr.add(LANES);
+
+                        // Process v2
+                        v2.storeu(dptr);
+                        if !mask2.all_zero() {
+                            let cn = mask2.first_offset();
+                            nb -= cn;
+                            dptr = dptr.add(cn);
+                            sptr = sptr.add(cn);
+                            escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+                            continue;
+                        }
+                        nb -= LANES;
+                        dptr = dptr.add(LANES);
+                        sptr = sptr.add(LANES);
+
+                        // Process v3
+                        v3.storeu(dptr);
+                        if !mask3.all_zero() {
+                            let cn = mask3.first_offset();
+                            nb -= cn;
+                            dptr = dptr.add(cn);
+                            sptr = sptr.add(cn);
+                            escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+                            continue;
+                        }
+                        nb -= LANES;
+                        dptr = dptr.add(LANES);
+                        sptr = sptr.add(LANES);
+
+                        // Process v4
+                        v4.storeu(dptr);
+                        if !mask4.all_zero() {
+                            let cn = mask4.first_offset();
+                            nb -= cn;
+                            dptr = dptr.add(cn);
+                            sptr = sptr.add(cn);
+                            escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+                            continue;
+                        }
+                        nb -= LANES;
+                        dptr = dptr.add(LANES);
+                        sptr = sptr.add(LANES);
+                    }
+                }
+
+                // Process remaining LANES bytes at a time
+                while nb >= LANES {
+                    let v = Simd128u::loadu(sptr);
+                    v.storeu(dptr);
+                    let mask = $escaped_mask(v);
+
+                    if mask.all_zero() {
+                        nb -= LANES;
+                        dptr = dptr.add(LANES);
+                        sptr = sptr.add(LANES);
+                    } else {
+                        let cn = mask.first_offset();
+                        nb -= cn;
+                        dptr = dptr.add(cn);
+                        sptr = sptr.add(cn);
+                        escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+                    }
+                }
+
+                // Handle remaining bytes
+                let mut placeholder: [u8; LANES] = [0; LANES];
+                while nb > 0 {
+                    let v = {
+                        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+                        {
+                            std::ptr::copy_nonoverlapping(sptr, placeholder[..].as_mut_ptr(), nb);
+                            Simd128u::loadu(placeholder[..].as_ptr())
+                        }
+                        #[cfg(any(target_os = "linux", target_os = "macos"))]
+                        {
+                            if check_cross_page(sptr, LANES) {
+                                std::ptr::copy_nonoverlapping(
+                                    sptr,
+                                    placeholder[..].as_mut_ptr(),
+                                    nb,
+                                );
+                                Simd128u::loadu(placeholder[..].as_ptr())
+                            } else {
+                                #[cfg(any(debug_assertions, miri, feature = "asan"))]
+                                {
+                                    std::ptr::copy_nonoverlapping(
+                                        sptr,
+                                        placeholder[..].as_mut_ptr(),
+                                        nb,
+                                    );
+                                    Simd128u::loadu(placeholder[..].as_ptr())
+                                }
+                                #[cfg(not(any(debug_assertions, miri)))]
+                                {
+                                    Simd128u::loadu(sptr)
+                                }
+                            }
+                        }
+                    };
+
+                    v.storeu(dptr);
+                    let mask = $escaped_mask(v).clear_high_bits(LANES - nb);
+
+                    if mask.all_zero() {
+                        dptr = dptr.add(nb);
+                        break;
+                    } else {
+                        let cn = mask.first_offset();
+                        nb -= cn;
+                        dptr = dptr.add(cn);
+                        sptr = sptr.add(cn);
+                        escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+                    }
+                }
+
+                *dptr = b'"';
+                dptr = dptr.add(1);
+                dptr as usize - dstart as usize
+            }
+        }
+    };
+}
+
+// z14 gathers the mask with `vbperm`; the z13 vector facility alone has to go
+// through memory and `movemask`.
+impl_format_string!(format_string, "vector", escaped_mask);
+impl_format_string!(
+    format_string_bperm,
+    "vector,vector-enhancements-1",
+    escaped_mask_bperm
+);
@@ -5,7 +5,11 @@ use std::arch::x86_64::*;
 
 use std::ops::{BitAnd, BitOr, BitOrAssign};
 
-use super::{Mask, Simd, traits::BitMask, util::escape_unchecked};
+use super::{
+    Mask, Simd,
+    traits::BitMask,
+    util::{SlowPath, escape_unchecked},
+};
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use super::util::check_cross_page;
@@ -101,7 +105,7 @@ fn escaped_mask(v: Simd128u) -> u16 {
 }
 
 #[target_feature(enable = "sse2")]
-pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
+pub unsafe fn format_string<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
     unsafe {
         let slice = value.as_bytes();
         let mut sptr = slice.as_ptr();
@@ -144,7 +148,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -158,7 +162,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -172,7 +176,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -186,7 +190,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                     nb -= cn;
                     dptr = dptr.add(cn);
                     sptr = sptr.add(cn);
-                    escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                    escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
                     continue;
                 }
                 nb -= LANES;
@@ -210,7 +214,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                 nb -= cn;
                 dptr = dptr.add(cn);
                 sptr = sptr.add(cn);
-                escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
             }
         }
 
@@ -253,7 +257,7 @@ pub unsafe fn format_string(value: &str, dst: &mut [u8]) -> usize {
                 nb -= cn;
                 dptr = dptr.add(cn);
                 sptr = sptr.add(cn);
-                escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
             }
         }
 
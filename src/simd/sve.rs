@@ -0,0 +1,268 @@
+//! ARM SVE/SVE2 kernel.
+//!
+//! Vector-length agnostic like `rvv`, so it bypasses the fixed-width
+//! `Simd`/`Mask` traits. `whilelo` builds a predicate covering at most `nb`
+//! lanes, so the tail is handled by the same predicated load/store as the body
+//! and never touches bytes past the end of the input. Like the fixed-width
+//! kernels' `CHUNK` loop, the body checks four vectors at a time while they
+//! need no escapes.
+
+use std::arch::asm;
+
+use super::util::{SlowPath, escape_unchecked};
+
+/// Copies up to `nb` bytes from `src` to `dst` with a single predicated vector
+/// and returns how many bytes were processed together with the number of bytes
+/// before the first one that needs escaping (equal to the first value if none).
+#[inline]
+#[target_feature(enable = "sve")]
+unsafe fn copy_until_escape(src: *const u8, dst: *mut u8, nb: usize) -> (usize, usize) {
+    let vl: usize;
+    let clean: usize;
+    unsafe {
+        asm!(
+            "whilelo p0.b, xzr, {nb}",
+            "ld1b {{z0.b}}, p0/z, [{src}]",
+            "mov z1.b, #34",
+            "mov z2.b, #92",
+            "cmpls p1.b, p0/z, z0.b, #31",
+            "cmpeq p2.b, p0/z, z0.b, z1.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpeq p2.b, p0/z, z0.b, z2.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "st1b {{z0.b}}, p0, [{dst}]",
+            // Keep only the active lanes before the first escape, then count.
+            "brkb p2.b, p0/z, p1.b",
+            "cntp {vl}, p0, p0.b",
+            "cntp {clean}, p0, p2.b",
+            vl = out(reg) vl,
+            clean = out(reg) clean,
+            nb = in(reg) nb,
+            src = in(reg) src,
+            dst = in(reg) dst,
+            out("v0") _,
+            out("v1") _,
+            out("v2") _,
+            out("p0") _,
+            out("p1") _,
+            out("p2") _,
+            options(nostack),
+        );
+    }
+    (vl, clean)
+}
+
+/// Same as [`copy_until_escape`], but classifies `"` and `\` with a single
+/// SVE2 `match` against a segment holding both bytes.
+#[inline]
+#[target_feature(enable = "sve2")]
+unsafe fn copy_until_escape_sve2(src: *const u8, dst: *mut u8, nb: usize) -> (usize, usize) {
+    let vl: usize;
+    let clean: usize;
+    unsafe {
+        asm!(
+            "whilelo p0.b, xzr, {nb}",
+            "ld1b {{z0.b}}, p0/z, [{src}]",
+            // Every halfword is `"\`, so each 128-bit segment contains both.
+            "dup z1.h, {pair:w}",
+            "cmpls p1.b, p0/z, z0.b, #31",
+            "match p2.b, p0/z, z0.b, z1.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "st1b {{z0.b}}, p0, [{dst}]",
+            "brkb p2.b, p0/z, p1.b",
+            "cntp {vl}, p0, p0.b",
+            "cntp {clean}, p0, p2.b",
+            vl = out(reg) vl,
+            clean = out(reg) clean,
+            nb = in(reg) nb,
+            src = in(reg) src,
+            dst = in(reg) dst,
+            pair = in(reg) u16::from_le_bytes([b'"', b'\\']) as u64,
+            out("v0") _,
+            out("v1") _,
+            out("p0") _,
+            out("p1") _,
+            out("p2") _,
+            options(nostack),
+        );
+    }
+    (vl, clean)
+}
+
+/// The SVE vector length in bytes.
+#[inline]
+#[target_feature(enable = "sve")]
+pub(crate) unsafe fn vector_bytes() -> usize {
+    let vl: usize;
+    unsafe { asm!("rdvl {vl}, #1", vl = out(reg) vl, options(pure, nomem, nostack)) };
+    vl
+}
+
+/// Copies the four vectors at `src` to `dst` and returns whether none of their
+/// bytes needs escaping. There must be at least `4 * vector_bytes()` of them.
+#[inline]
+#[target_feature(enable = "sve")]
+unsafe fn copy4_if_clean(src: *const u8, dst: *mut u8) -> bool {
+    let dirty: u64;
+    unsafe {
+        asm!(
+            "ptrue p0.b",
+            "ld1b {{z0.b}}, p0/z, [{src}]",
+            "ld1b {{z1.b}}, p0/z, [{src}, #1, mul vl]",
+            "ld1b {{z2.b}}, p0/z, [{src}, #2, mul vl]",
+            "ld1b {{z3.b}}, p0/z, [{src}, #3, mul vl]",
+            "st1b {{z0.b}}, p0, [{dst}]",
+            "st1b {{z1.b}}, p0, [{dst}, #1, mul vl]",
+            "st1b {{z2.b}}, p0, [{dst}, #2, mul vl]",
+            "st1b {{z3.b}}, p0, [{dst}, #3, mul vl]",
+            "mov z4.b, #34",
+            "mov z5.b, #92",
+            // Accumulate the escapes of all four vectors in p1.
+            "cmpls p1.b, p0/z, z0.b, #31",
+            "cmpeq p2.b, p0/z, z0.b, z4.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpeq p2.b, p0/z, z0.b, z5.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpls p2.b, p0/z, z1.b, #31",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpeq p2.b, p0/z, z1.b, z4.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpeq p2.b, p0/z, z1.b, z5.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpls p2.b, p0/z, z2.b, #31",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpeq p2.b, p0/z, z2.b, z4.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpeq p2.b, p0/z, z2.b, z5.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpls p2.b, p0/z, z3.b, #31",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpeq p2.b, p0/z, z3.b, z4.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpeq p2.b, p0/z, z3.b, z5.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "ptest p0, p1.b",
+            "cset {dirty}, ne",
+            dirty = out(reg) dirty,
+            src = in(reg) src,
+            dst = in(reg) dst,
+            out("v0") _,
+            out("v1") _,
+            out("v2") _,
+            out("v3") _,
+            out("v4") _,
+            out("v5") _,
+            out("p0") _,
+            out("p1") _,
+            out("p2") _,
+            options(nostack),
+        );
+    }
+    dirty == 0
+}
+
+/// Same as [`copy4_if_clean`], with the SVE2 `match` of
+/// [`copy_until_escape_sve2`].
+#[inline]
+#[target_feature(enable = "sve2")]
+unsafe fn copy4_if_clean_sve2(src: *const u8, dst: *mut u8) -> bool {
+    let dirty: u64;
+    unsafe {
+        asm!(
+            "ptrue p0.b",
+            "ld1b {{z0.b}}, p0/z, [{src}]",
+            "ld1b {{z1.b}}, p0/z, [{src}, #1, mul vl]",
+            "ld1b {{z2.b}}, p0/z, [{src}, #2, mul vl]",
+            "ld1b {{z3.b}}, p0/z, [{src}, #3, mul vl]",
+            "st1b {{z0.b}}, p0, [{dst}]",
+            "st1b {{z1.b}}, p0, [{dst}, #1, mul vl]",
+            "st1b {{z2.b}}, p0, [{dst}, #2, mul vl]",
+            "st1b {{z3.b}}, p0, [{dst}, #3, mul vl]",
+            "dup z4.h, {pair:w}",
+            "cmpls p1.b, p0/z, z0.b, #31",
+            "match p2.b, p0/z, z0.b, z4.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpls p2.b, p0/z, z1.b, #31",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "match p2.b, p0/z, z1.b, z4.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpls p2.b, p0/z, z2.b, #31",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "match p2.b, p0/z, z2.b, z4.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "cmpls p2.b, p0/z, z3.b, #31",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "match p2.b, p0/z, z3.b, z4.b",
+            "orr p1.b, p0/z, p1.b, p2.b",
+            "ptest p0, p1.b",
+            "cset {dirty}, ne",
+            dirty = out(reg) dirty,
+            src = in(reg) src,
+            dst = in(reg) dst,
+            pair = in(reg) u16::from_le_bytes([b'"', b'\\']) as u64,
+            out("v0") _,
+            out("v1") _,
+            out("v2") _,
+            out("v3") _,
+            out("v4") _,
+            out("p0") _,
+            out("p1") _,
+            out("p2") _,
+            options(nostack),
+        );
+    }
+    dirty == 0
+}
+
+macro_rules! impl_format_string {
+    ($name:ident, $feature:literal, $copy:ident, $copy4:ident) => {
+        #[target_feature(enable = $feature)]
+        pub unsafe fn $name<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
+            unsafe {
+                let slice = value.as_bytes();
+                let mut sptr = slice.as_ptr();
+                let mut dptr = dst.as_mut_ptr();
+                let dstart = dptr;
+                let mut nb: usize = slice.len();
+                let block = 4 * vector_bytes();
+
+                *dptr = b'"';
+                dptr = dptr.add(1);
+
+                while nb > 0 {
+                    // Blocks of four vectors without escapes go in one step.
+                    if nb >= block && $copy4(sptr, dptr) {
+                        nb -= block;
+                        dptr = dptr.add(block);
+                        sptr = sptr.add(block);
+                        continue;
+                    }
+                    // Otherwise escape the block, or the tail, one vector at a
+                    // time before trying the next one.
+                    let stop = nb.saturating_sub(block);
+                    while nb > stop {
+                        let (vl, cn) = $copy(sptr, dptr, nb);
+                        nb -= cn;
+                        dptr = dptr.add(cn);
+                        sptr = sptr.add(cn);
+                        if cn != vl {
+                            escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+                        }
+                    }
+                }
+
+                *dptr = b'"';
+                dptr = dptr.add(1);
+                dptr as usize - dstart as usize
+            }
+        }
+    };
+}
+
+impl_format_string!(format_string, "sve", copy_until_escape, copy4_if_clean);
+impl_format_string!(
+    format_string_sve2,
+    "sve2",
+    copy_until_escape_sve2,
+    copy4_if_clean_sve2
+);
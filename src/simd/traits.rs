@@ -2,6 +2,9 @@ use std::ops::{BitAnd, BitOr, BitOrAssign};
 
 /// Portable SIMD traits
 pub trait Simd: Sized {
+    // Only read through the trait by `v128`, which is compiled out when a
+    // SIMD kernel is selected at compile time.
+    #[allow(dead_code)]
     const LANES: usize;
 
     type Element;
@@ -18,6 +21,9 @@ pub trait Simd: Sized {
     fn splat(elem: Self::Element) -> Self;
 
     /// less or equal
+    // `v128` specializes this for a constant, so it is unused on targets that
+    // compile no other kernel.
+    #[allow(dead_code)]
     fn le(&self, rhs: &Self) -> Self::Mask;
 }
 
@@ -32,8 +38,9 @@ pub trait Mask: Sized + BitOr<Self> + BitOrAssign + BitAnd<Self> {
 /// Trait for the bitmask of a vector Mask.
 pub trait BitMask {
     /// Total bits in the bitmask.
-    // Used only by the wide-SIMD kernels (sse2/avx2/avx512/neon); dead code on
-    // scalar-only targets like s390x that compile just `v128`.
+    // Only read by `clear_high_bits`, which is dead code on targets that
+    // compile just `v128`, such as powerpc64 and s390x without the `nightly`
+    // feature.
     #[allow(dead_code)]
     const LEN: usize;
 
@@ -1,10 +1,41 @@
 use crate::{NEED_ESCAPED, QUOTE_TAB};
 
-#[inline(always)]
-pub(crate) unsafe fn escape_unchecked(src: &mut *const u8, nb: &mut usize, dst: &mut *mut u8) {
-    debug_assert!(*nb >= 1);
-    loop {
-        let ch = unsafe { *(*src) };
+/// What the kernels write for each byte they find needs escaping: the slow
+/// path, which their SIMD loops hand over to at the first such byte.
+///
+/// [`Quote`] is the JSON escape of every one of them. Other implementations
+/// treat some control characters differently, without slowing down the loops.
+pub(crate) trait SlowPath: Copy {
+    /// Whether the dense paths may write the two-byte escapes of control
+    /// characters themselves, instead of handing them to [`escape_byte`].
+    ///
+    /// [`escape_byte`]: SlowPath::escape_byte
+    #[cfg_attr(
+        not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")),
+        allow(dead_code)
+    )]
+    #[inline(always)]
+    fn short_controls(self) -> bool {
+        true
+    }
+
+    /// Writes what `*src`, a byte that needs escaping, becomes at `*dst` and
+    /// advances `dst` past it. May write up to 8 bytes.
+    ///
+    /// # Safety
+    ///
+    /// `src` must be readable and `*dst` writable for 8 bytes.
+    unsafe fn escape_byte(self, src: *const u8, dst: &mut *mut u8);
+}
+
+/// The JSON escapes of [`QUOTE_TAB`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Quote;
+
+impl SlowPath for Quote {
+    #[inline(always)]
+    unsafe fn escape_byte(self, src: *const u8, dst: &mut *mut u8) {
+        let ch = unsafe { *src };
         let cnt = QUOTE_TAB[ch as usize].0 as usize;
         debug_assert!(
             cnt != 0,
@@ -15,6 +46,19 @@ pub(crate) unsafe fn escape_unchecked(src: &mut *const u8, nb: &mut usize, dst:
         );
         unsafe { std::ptr::copy_nonoverlapping(QUOTE_TAB[ch as usize].1.as_ptr(), *dst, 8) };
         unsafe { (*dst) = (*dst).add(cnt) };
+    }
+}
+
+#[inline(always)]
+pub(crate) unsafe fn escape_unchecked<P: SlowPath>(
+    src: &mut *const u8,
+    nb: &mut usize,
+    dst: &mut *mut u8,
+    slow: P,
+) {
+    debug_assert!(*nb >= 1);
+    loop {
+        unsafe { slow.escape_byte(*src, dst) };
         unsafe { (*src) = (*src).add(1) };
         (*nb) -= 1;
         if (*nb) == 0 || unsafe { NEED_ESCAPED[*(*src) as usize] == 0 } {
@@ -23,7 +67,83 @@ pub(crate) unsafe fn escape_unchecked(src: &mut *const u8, nb: &mut usize, dst:
     }
 }
 
+/// Minimum number of escapes in a 16-byte block for [`EXPAND_SHUFFLE`] to beat
+/// escaping the bytes one at a time.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+#[allow(dead_code)]
+pub(crate) const DENSE_ESCAPES: u32 = 2;
+
+/// Second byte of the two-byte escapes of the control characters, indexed by
+/// the character, or 0 where it needs `\u00XX`. Every one of them is below
+/// 0x10, so a 16-byte table lookup covers them all.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+#[allow(dead_code)]
+pub(crate) const SHORT_ESCAPES: [u8; 16] = [
+    0, 0, 0, 0, 0, 0, 0, 0, b'b', b't', b'n', 0, b'f', b'r', 0, 0,
+];
+
+/// Byte shuffles that expand 8 bytes, the escaped ones flagged in the index,
+/// into `8 + index.count_ones()` bytes where each escaped byte is preceded by a
+/// backslash. The source register holds the 8 bytes (with escape letters
+/// already substituted) in lanes 0..8 and `\` in lanes 8..16.
+// Used by the kernels that expand dense escapes with a table shuffle, which are
+// not compiled when AVX-512 is statically guaranteed.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+#[allow(dead_code)]
+pub(crate) static EXPAND_SHUFFLE: [[u8; 16]; 256] = {
+    let mut tab = [[0; 16]; 256];
+    let mut mask = 0;
+    while mask < 256 {
+        let mut out = 0;
+        let mut i = 0;
+        while i < 8 {
+            if mask & (1 << i) != 0 {
+                tab[mask][out] = 8;
+                out += 1;
+            }
+            tab[mask][out] = i as u8;
+            out += 1;
+            i += 1;
+        }
+        mask += 1;
+    }
+    tab
+};
+
+/// Packs the most significant bit of each byte of a little-endian word into a
+/// `u8`, byte `i` -> bit `i`.
+// Only the kernels without a `movemask` instruction use this, so it is dead
+// code when a SIMD kernel is statically guaranteed by the target.
+#[allow(dead_code)]
+#[inline(always)]
+pub(crate) fn movemask_word(word: u64) -> u8 {
+    // Gather the eight MSBs into the top byte: the multiplier shifts the MSB of
+    // byte `i` to bit `56 + i` without any carries colliding.
+    (((word & 0x8080_8080_8080_8080).wrapping_mul(0x0002_0408_1020_4081)) >> 56) as u8
+}
+
+/// Packs the most significant bit of each byte into a `u16`, byte `i` -> bit
+/// `i`, for ISAs without a `movemask` instruction.
+///
+/// The words are read as little endian on purpose: that keeps the lane order
+/// canonical on big-endian targets too, where a native-endian read would
+/// reverse it.
+#[cfg(all(
+    any(target_arch = "powerpc64", target_arch = "s390x"),
+    feature = "nightly"
+))]
+#[inline(always)]
+pub(crate) fn movemask(bytes: [u8; 16]) -> u16 {
+    let (lo, hi) = bytes.split_at(8);
+    let lo = u64::from_le_bytes(lo.try_into().unwrap());
+    let hi = u64::from_le_bytes(hi.try_into().unwrap());
+    movemask_word(lo) as u16 | (movemask_word(hi) as u16) << 8
+}
+
 #[cfg(any(target_os = "linux", target_os = "macos"))]
+// The avx512 kernel masks its tail loads instead, so this is dead code when it
+// is the only kernel compiled in.
+#[allow(dead_code)]
 #[inline(always)]
 pub(crate) fn check_cross_page(ptr: *const u8, step: usize) -> bool {
     let page_size = 4096;
@@ -1,55 +1,85 @@
+//! Scalar fallback that emulates 16 lanes with SWAR (SIMD within a register)
+//! bit tricks on two `u64` words.
+//!
+//! Lanes are loaded as little-endian words so that lane `i` is byte `i` of the
+//! word on every target, and a mask lane is "set" when its most significant bit
+//! is. The compares are exact per lane: no carry or borrow crosses a byte.
+
 use std::ops::{BitAnd, BitOr, BitOrAssign};
 
 use crate::simd::traits::BitMask;
 
-use super::{Mask, Simd, util::escape_unchecked};
+use super::{
+    Mask, Simd,
+    util::{SlowPath, escape_unchecked, movemask_word},
+};
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use super::util::check_cross_page;
 
 const LANES: usize = 16;
+const CHUNK: usize = LANES * 4;
+
+/// The most significant bit of every byte.
+const HI: u64 = 0x8080_8080_8080_8080;
+/// The low seven bits of every byte.
+const LO: u64 = !HI;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Simd128u([u64; 2]);
 
-#[derive(Debug)]
-pub struct Simd128u([u8; 16]);
+#[derive(Debug, Clone, Copy)]
+pub struct Mask128([u64; 2]);
 
-#[derive(Debug)]
-pub struct Mask128(pub(crate) [u8; 16]);
+/// Sets the MSB of every byte of `a` that is equal to the same byte of `b`.
+#[inline(always)]
+fn eq_word(a: u64, b: u64) -> u64 {
+    let x = a ^ b;
+    // Adding 0x7f to the low seven bits carries into the MSB unless they are
+    // all zero; OR-ing `x` back in catches bytes whose own MSB differs.
+    !(((x & LO) + LO) | x) & HI
+}
 
 impl Simd for Simd128u {
     type Element = u8;
     const LANES: usize = 16;
     type Mask = Mask128;
 
+    #[inline(always)]
     unsafe fn loadu(ptr: *const u8) -> Self {
-        let v = unsafe { std::slice::from_raw_parts(ptr, Self::LANES) };
-        let mut res = [0u8; 16];
-        res.copy_from_slice(v);
-        Self(res)
+        let bytes = unsafe { ptr.cast::<[[u8; 8]; 2]>().read_unaligned() };
+        Self([u64::from_le_bytes(bytes[0]), u64::from_le_bytes(bytes[1])])
     }
 
+    #[inline(always)]
     unsafe fn storeu(&self, ptr: *mut u8) {
-        let data = &self.0;
-        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, Self::LANES) };
+        let bytes = [self.0[0].to_le_bytes(), self.0[1].to_le_bytes()];
+        unsafe { ptr.cast::<[[u8; 8]; 2]>().write_unaligned(bytes) };
     }
 
+    #[inline(always)]
     fn eq(&self, rhs: &Self) -> Self::Mask {
-        let mut mask = [0u8; 16];
-        for (i, item) in mask.iter_mut().enumerate().take(Self::LANES) {
-            *item = if self.0[i] == rhs.0[i] { 1 } else { 0 };
-        }
-        Mask128(mask)
+        Mask128([eq_word(self.0[0], rhs.0[0]), eq_word(self.0[1], rhs.0[1])])
     }
 
+    #[inline(always)]
     fn splat(value: u8) -> Self {
-        Self([value; Self::LANES])
+        Self([splat_word(value); 2])
     }
 
+    // `escaped_mask` uses the cheaper has-less-than test against a constant.
+    #[inline(always)]
     fn le(&self, rhs: &Self) -> Self::Mask {
-        let mut mask = [0u8; 16];
-        for (i, item) in mask.iter_mut().enumerate().take(Self::LANES) {
-            *item = if self.0[i] <= rhs.0[i] { 1 } else { 0 };
+        #[inline(always)]
+        fn word(a: u64, b: u64) -> u64 {
+            // Per-byte `b - a` with the MSBs computed separately so that no
+            // borrow leaves a byte, then recover each byte's borrow out:
+            // `a <= b` iff there is none.
+            let diff = ((b | HI) - (a & LO)) ^ ((b ^ !a) & HI);
+            let borrow = (!b & a) | (!(a ^ b) & diff);
+            !borrow & HI
         }
-        Mask128(mask)
+        Mask128([word(self.0[0], rhs.0[0]), word(self.0[1], rhs.0[1])])
     }
 }
 
@@ -57,59 +87,70 @@ impl Mask for Mask128 {
     type BitMask = u16;
     type Element = u8;
 
+    #[inline(always)]
     fn bitmask(self) -> Self::BitMask {
-        // Built in software, so the bitmask has no inherent byte order: lane
-        // `i` maps to bit `i` on every target, matching `first_offset`'s plain
-        // `trailing_zeros`.
-        self.0
-            .iter()
-            .enumerate()
-            .fold(0, |acc, (i, &b)| acc | ((b as u16) << i))
+        movemask_word(self.0[0]) as u16 | (movemask_word(self.0[1]) as u16) << 8
     }
 }
 
 impl BitAnd for Mask128 {
     type Output = Self;
 
+    #[inline(always)]
     fn bitand(self, rhs: Self) -> Self::Output {
-        let mut result = [0u8; 16];
-        for (i, item) in result.iter_mut().enumerate() {
-            *item = self.0[i] & rhs.0[i];
-        }
-        Mask128(result)
+        Mask128([self.0[0] & rhs.0[0], self.0[1] & rhs.0[1]])
     }
 }
 
 impl BitOr for Mask128 {
     type Output = Self;
 
+    #[inline(always)]
     fn bitor(self, rhs: Self) -> Self::Output {
-        let mut result = [0u8; 16];
-        for (i, item) in result.iter_mut().enumerate() {
-            *item = self.0[i] | rhs.0[i];
-        }
-        Mask128(result)
+        Mask128([self.0[0] | rhs.0[0], self.0[1] | rhs.0[1]])
     }
 }
 
 impl BitOrAssign for Mask128 {
+    #[inline(always)]
     fn bitor_assign(&mut self, rhs: Self) {
-        for i in 0..16 {
-            self.0[i] |= rhs.0[i];
+        self.0[0] |= rhs.0[0];
+        self.0[1] |= rhs.0[1];
+    }
+}
+
+const fn splat_word(value: u8) -> u64 {
+    u64::from_ne_bytes([value; 8])
+}
+
+impl Mask128 {
+    #[inline(always)]
+    fn all_zero(&self) -> bool {
+        self.0[0] | self.0[1] == 0
+    }
+
+    /// Offset of the first set lane, without packing the mask into bits first.
+    #[inline(always)]
+    fn first_offset(&self) -> usize {
+        if self.0[0] != 0 {
+            self.0[0].trailing_zeros() as usize / 8
+        } else {
+            8 + self.0[1].trailing_zeros() as usize / 8
         }
     }
 }
 
 #[inline(always)]
-fn escaped_mask(v: Simd128u) -> u16 {
-    let x1f = Simd128u::splat(0x1f); // 0x00 ~ 0x20
+fn escaped_mask(v: Simd128u) -> Mask128 {
+    // `le(0x1f)` specialized to the has-less-than trick: adding 0x60 to the low
+    // seven bits reaches the MSB iff they are at least 0x20.
+    let control = |w: u64| !(((w & LO) + splat_word(0x80 - 0x20)) | w) & HI;
     let blash = Simd128u::splat(b'\\');
     let quote = Simd128u::splat(b'"');
-    let v = v.le(&x1f) | v.eq(&blash) | v.eq(&quote);
-    v.bitmask()
+    Mask128([control(v.0[0]), control(v.0[1])]) | v.eq(&blash) | v.eq(&quote)
 }
 
-pub fn format_string(value: &str, dst: &mut [u8]) -> usize {
+pub fn format_string<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
     unsafe {
         let slice = value.as_bytes();
         let mut sptr = slice.as_ptr();
@@ -120,13 +161,33 @@ pub fn format_string(value: &str, dst: &mut [u8]) -> usize {
         *dptr = b'"';
         dptr = dptr.add(1);
 
+        // Process CHUNK (4 * LANES = 64 bytes) at a time while it is clean, so
+        // the masks of independent words can be computed in parallel.
+        while nb >= CHUNK {
+            let v1 = Simd128u::loadu(sptr);
+            let v2 = Simd128u::loadu(sptr.add(LANES));
+            let v3 = Simd128u::loadu(sptr.add(LANES * 2));
+            let v4 = Simd128u::loadu(sptr.add(LANES * 3));
+            let mask = escaped_mask(v1) | escaped_mask(v2) | escaped_mask(v3) | escaped_mask(v4);
+            if !mask.all_zero() {
+                break;
+            }
+            v1.storeu(dptr);
+            v2.storeu(dptr.add(LANES));
+            v3.storeu(dptr.add(LANES * 2));
+            v4.storeu(dptr.add(LANES * 3));
+            nb -= CHUNK;
+            dptr = dptr.add(CHUNK);
+            sptr = sptr.add(CHUNK);
+        }
+
         // Main loop: process LANES bytes at a time
         while nb >= LANES {
             let v = Simd128u::loadu(sptr);
             v.storeu(dptr);
             let mask = escaped_mask(v);
 
-            if mask == 0 {
+            if mask.all_zero() {
                 nb -= LANES;
                 dptr = dptr.add(LANES);
                 sptr = sptr.add(LANES);
@@ -135,7 +196,7 @@ pub fn format_string(value: &str, dst: &mut [u8]) -> usize {
                 nb -= cn;
                 dptr = dptr.add(cn);
                 sptr = sptr.add(cn);
-                escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
             }
         }
 
@@ -168,7 +229,7 @@ pub fn format_string(value: &str, dst: &mut [u8]) -> usize {
             };
 
             v.storeu(dptr);
-            let mut mask = escaped_mask(v);
+            let mut mask = escaped_mask(v).bitmask();
             // Clear high bits for partial vector
             mask &= (1u16 << nb) - 1;
 
@@ -180,7 +241,7 @@ pub fn format_string(value: &str, dst: &mut [u8]) -> usize {
                 nb -= cn;
                 dptr = dptr.add(cn);
                 sptr = sptr.add(cn);
-                escape_unchecked(&mut sptr, &mut nb, &mut dptr);
+                escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
             }
         }
 
@@ -0,0 +1,240 @@
+use std::arch::wasm32::*;
+
+use std::ops::{BitAnd, BitOr, BitOrAssign};
+
+use super::{
+    Mask, Simd,
+    traits::BitMask,
+    util::{SlowPath, escape_unchecked},
+};
+
+const LANES: usize = 16;
+const CHUNK: usize = LANES * 4;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+pub struct Simd128u(v128);
+
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+pub struct Mask128(v128);
+
+impl Mask for Mask128 {
+    type BitMask = u16;
+    type Element = u8;
+
+    #[inline(always)]
+    fn bitmask(self) -> Self::BitMask {
+        i8x16_bitmask(self.0)
+    }
+}
+
+impl BitAnd<Mask128> for Mask128 {
+    type Output = Self;
+
+    #[inline(always)]
+    fn bitand(self, rhs: Mask128) -> Self::Output {
+        Mask128(v128_and(self.0, rhs.0))
+    }
+}
+
+impl BitOr<Mask128> for Mask128 {
+    type Output = Self;
+
+    #[inline(always)]
+    fn bitor(self, rhs: Mask128) -> Self::Output {
+        Mask128(v128_or(self.0, rhs.0))
+    }
+}
+
+impl BitOrAssign<Mask128> for Mask128 {
+    #[inline(always)]
+    fn bitor_assign(&mut self, rhs: Mask128) {
+        self.0 = v128_or(self.0, rhs.0);
+    }
+}
+
+impl Simd for Simd128u {
+    const LANES: usize = LANES;
+    type Mask = Mask128;
+    type Element = u8;
+
+    #[inline(always)]
+    unsafe fn loadu(ptr: *const u8) -> Self {
+        // `v128_load` has no alignment requirement.
+        Simd128u(unsafe { v128_load(ptr as *const v128) })
+    }
+
+    #[inline(always)]
+    unsafe fn storeu(&self, ptr: *mut u8) {
+        unsafe { v128_store(ptr as *mut v128, self.0) }
+    }
+
+    #[inline(always)]
+    fn eq(&self, rhs: &Self) -> Self::Mask {
+        Mask128(i8x16_eq(self.0, rhs.0))
+    }
+
+    #[inline(always)]
+    fn splat(ch: u8) -> Self {
+        Simd128u(u8x16_splat(ch))
+    }
+
+    #[inline(always)]
+    fn le(&self, rhs: &Self) -> Self::Mask {
+        Mask128(u8x16_le(self.0, rhs.0))
+    }
+}
+
+#[inline(always)]
+fn escaped_mask(v: Simd128u) -> u16 {
+    let x1f = Simd128u::splat(0x1f); // 0x00 ~ 0x20
+    let blash = Simd128u::splat(b'\\');
+    let quote = Simd128u::splat(b'"');
+    let v = v.le(&x1f) | v.eq(&blash) | v.eq(&quote);
+    v.bitmask()
+}
+
+// Only compiled when `simd128` is enabled for the whole build, since wasm has
+// no runtime feature detection.
+pub fn format_string<P: SlowPath>(value: &str, dst: &mut [u8], slow: P) -> usize {
+    unsafe {
+        let slice = value.as_bytes();
+        let mut sptr = slice.as_ptr();
+        let mut dptr = dst.as_mut_ptr();
+        let dstart = dptr;
+        let mut nb: usize = slice.len();
+
+        *dptr = b'"';
+        dptr = dptr.add(1);
+
+        // Process CHUNK (4 * LANES = 64 bytes) at a time
+        while nb >= CHUNK {
+            // Load 4 SIMD vectors
+            let v1 = Simd128u::loadu(sptr);
+            let v2 = Simd128u::loadu(sptr.add(LANES));
+            let v3 = Simd128u::loadu(sptr.add(LANES * 2));
+            let v4 = Simd128u::loadu(sptr.add(LANES * 3));
+
+            // Check all 4 masks
+            let mask1 = escaped_mask(v1);
+            let mask2 = escaped_mask(v2);
+            let mask3 = escaped_mask(v3);
+            let mask4 = escaped_mask(v4);
+
+            // Fast path: if all vectors are clean, write the entire chunk
+            if mask1.all_zero() && mask2.all_zero() && mask3.all_zero() && mask4.all_zero() {
+                v1.storeu(dptr);
+                v2.storeu(dptr.add(LANES));
+                v3.storeu(dptr.add(LANES * 2));
+                v4.storeu(dptr.add(LANES * 3));
+                nb -= CHUNK;
+                dptr = dptr.add(CHUNK);
+                sptr = sptr.add(CHUNK);
+            } else {
+                // Slow path: handle escape character
+                // Process v1
+                v1.storeu(dptr);
+                if !mask1.all_zero() {
+                    let cn = mask1.first_offset();
+                    nb -= cn;
+                    dptr = dptr.add(cn);
+                    sptr = sptr.add(cn);
+                    escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+                    continue;
+                }
+                nb -= LANES;
+                dptr = dptr.add(LANES);
+                sptr = sptr.add(LANES);
+
+                // Process v2
+                v2.storeu(dptr);
+                if !mask2.all_zero() {
+                    let cn = mask2.first_offset();
+                    nb -= cn;
+                    dptr = dptr.add(cn);
+                    sptr = sptr.add(cn);
+                    escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+                    continue;
+                }
+                nb -= LANES;
+                dptr = dptr.add(LANES);
+                sptr = sptr.add(LANES);
+
+                // Process v3
+                v3.storeu(dptr);
+                if !mask3.all_zero() {
+                    let cn = mask3.first_offset();
+                    nb -= cn;
+                    dptr = dptr.add(cn);
+                    sptr = sptr.add(cn);
+                    escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+                    continue;
+                }
+                nb -= LANES;
+                dptr = dptr.add(LANES);
+                sptr = sptr.add(LANES);
+
+                // Process v4
+                v4.storeu(dptr);
+                if !mask4.all_zero() {
+                    let cn = mask4.first_offset();
+                    nb -= cn;
+                    dptr = dptr.add(cn);
+                    sptr = sptr.add(cn);
+                    escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+                    continue;
+                }
+                nb -= LANES;
+                dptr = dptr.add(LANES);
+                sptr = sptr.add(LANES);
+            }
+        }
+
+        // Process remaining LANES bytes at a time
+        while nb >= LANES {
+            let v = Simd128u::loadu(sptr);
+            v.storeu(dptr);
+            let mask = escaped_mask(v);
+
+            if mask.all_zero() {
+                nb -= LANES;
+                dptr = dptr.add(LANES);
+                sptr = sptr.add(LANES);
+            } else {
+                let cn = mask.first_offset();
+                nb -= cn;
+                dptr = dptr.add(cn);
+                sptr = sptr.add(cn);
+                escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+            }
+        }
+
+        // Handle remaining bytes
+        let mut placeholder: [u8; LANES] = [0; LANES];
+        while nb > 0 {
+            // Reading past the end of linear memory traps, and there is no page
+            // size guarantee to lean on, so always go through the placeholder.
+            std::ptr::copy_nonoverlapping(sptr, placeholder[..].as_mut_ptr(), nb);
+            let v = Simd128u::loadu(placeholder[..].as_ptr());
+
+            v.storeu(dptr);
+            let mask = escaped_mask(v).clear_high_bits(LANES - nb);
+
+            if mask.all_zero() {
+                dptr = dptr.add(nb);
+                break;
+            } else {
+                let cn = mask.first_offset();
+                nb -= cn;
+                dptr = dptr.add(cn);
+                sptr = sptr.add(cn);
+                escape_unchecked(&mut sptr, &mut nb, &mut dptr, slow);
+            }
+        }
+
+        *dptr = b'"';
+        dptr = dptr.add(1);
+        dptr as usize - dstart as usize
+    }
+}
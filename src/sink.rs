@@ -0,0 +1,121 @@
+//! Buffers that escaped output can be appended to.
+
+use std::mem::MaybeUninit;
+
+/// A byte buffer that [`escape_to`](crate::escape_to) appends escaped output
+/// to.
+///
+/// The kernels write straight into the buffer's spare capacity: they ask for
+/// room with [`reserve_spare`](Self::reserve_spare), write (and scribble) into
+/// it, then [`commit`](Self::commit) the bytes that belong to the output.
+///
+/// # Safety
+///
+/// The room [`reserve_spare`](Self::reserve_spare) returns must be the memory
+/// right past the end of the buffer, where [`commit`](Self::commit) appends
+/// from, and stay so until the next call to either.
+pub unsafe trait EscapeSink {
+    /// Makes room for at least `additional` more bytes and returns the spare
+    /// room past the end of the buffer.
+    ///
+    /// Buffers that cannot grow return whatever room they have left, which may
+    /// be less than `additional`.
+    fn reserve_spare(&mut self, additional: usize) -> &mut [MaybeUninit<u8>];
+
+    /// Appends the first `n` bytes of the room returned by the last
+    /// [`reserve_spare`](Self::reserve_spare).
+    ///
+    /// # Safety
+    ///
+    /// Those bytes must be initialized and, for buffers that hold UTF-8, valid
+    /// UTF-8 when appended to the current contents.
+    unsafe fn commit(&mut self, n: usize);
+}
+
+unsafe impl EscapeSink for Vec<u8> {
+    #[inline]
+    fn reserve_spare(&mut self, additional: usize) -> &mut [MaybeUninit<u8>] {
+        self.reserve(additional);
+        self.spare_capacity_mut()
+    }
+
+    #[inline]
+    unsafe fn commit(&mut self, n: usize) {
+        unsafe { self.set_len(self.len() + n) }
+    }
+}
+
+unsafe impl EscapeSink for String {
+    #[inline]
+    fn reserve_spare(&mut self, additional: usize) -> &mut [MaybeUninit<u8>] {
+        // SAFETY: only `commit` changes the contents, and its caller promises
+        // valid UTF-8.
+        unsafe { self.as_mut_vec() }.reserve_spare(additional)
+    }
+
+    #[inline]
+    unsafe fn commit(&mut self, n: usize) {
+        unsafe { self.as_mut_vec().commit(n) }
+    }
+}
+
+#[cfg(feature = "allocator-api2")]
+unsafe impl<A: allocator_api2::alloc::Allocator> EscapeSink for allocator_api2::vec::Vec<u8, A> {
+    #[inline]
+    fn reserve_spare(&mut self, additional: usize) -> &mut [MaybeUninit<u8>] {
+        self.reserve(additional);
+        self.spare_capacity_mut()
+    }
+
+    #[inline]
+    unsafe fn commit(&mut self, n: usize) {
+        unsafe { self.set_len(self.len() + n) }
+    }
+}
+
+#[cfg(feature = "bytes")]
+unsafe impl EscapeSink for bytes::BytesMut {
+    #[inline]
+    fn reserve_spare(&mut self, additional: usize) -> &mut [MaybeUninit<u8>] {
+        self.reserve(additional);
+        self.spare_capacity_mut()
+    }
+
+    #[inline]
+    unsafe fn commit(&mut self, n: usize) {
+        unsafe { self.set_len(self.len() + n) }
+    }
+}
+
+#[cfg(feature = "smallvec")]
+unsafe impl<A: smallvec::Array<Item = u8>> EscapeSink for smallvec::SmallVec<A> {
+    #[inline]
+    fn reserve_spare(&mut self, additional: usize) -> &mut [MaybeUninit<u8>] {
+        self.reserve(additional);
+        let len = self.len();
+        let spare = self.capacity() - len;
+        // SAFETY: the `spare` bytes past `len` are allocated, if uninitialized.
+        unsafe { std::slice::from_raw_parts_mut(self.as_mut_ptr().add(len).cast(), spare) }
+    }
+
+    #[inline]
+    unsafe fn commit(&mut self, n: usize) {
+        unsafe { self.set_len(self.len() + n) }
+    }
+}
+
+/// Never grows: escaping more than fits panics.
+#[cfg(feature = "arrayvec")]
+unsafe impl<const CAP: usize> EscapeSink for arrayvec::ArrayVec<u8, CAP> {
+    #[inline]
+    fn reserve_spare(&mut self, _additional: usize) -> &mut [MaybeUninit<u8>] {
+        let len = self.len();
+        // SAFETY: the `CAP - len` bytes past `len` are part of the array.
+        unsafe { std::slice::from_raw_parts_mut(self.as_mut_ptr().add(len).cast(), CAP - len) }
+    }
+
+    #[inline]
+    unsafe fn commit(&mut self, n: usize) {
+        unsafe { self.set_len(self.len() + n) }
+    }
+}
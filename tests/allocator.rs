@@ -0,0 +1,113 @@
+//! Escaping with a custom allocator never touches the global heap.
+//!
+//! A counting global allocator tracks this thread's heap allocations while a
+//! bump arena serves `escape_in` and `escape_to` into an arena-backed `Vec`.
+
+#![cfg(feature = "allocator-api2")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::{Cell, UnsafeCell};
+use std::ptr::NonNull;
+
+use allocator_api2::alloc::{AllocError, Allocator};
+use allocator_api2::vec::Vec as AllocVec;
+use json_escape_simd::{escape, escape_in, escape_to};
+
+struct CountingAlloc;
+
+thread_local! {
+    static GLOBAL_ALLOCS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    let _ = GLOBAL_ALLOCS.try_with(|n| n.set(n.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// A bump arena over a fixed buffer that never frees.
+struct Arena {
+    buf: Box<[UnsafeCell<u8>]>,
+    used: Cell<usize>,
+    allocs: Cell<usize>,
+}
+
+impl Arena {
+    fn new() -> Self {
+        Arena {
+            buf: (0..1 << 20).map(|_| UnsafeCell::new(0)).collect(),
+            used: Cell::new(0),
+            allocs: Cell::new(0),
+        }
+    }
+}
+
+unsafe impl Allocator for &Arena {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let base = UnsafeCell::raw_get(self.buf.as_ptr());
+        let start =
+            (base as usize + self.used.get()).next_multiple_of(layout.align()) - base as usize;
+        let end = start.checked_add(layout.size()).ok_or(AllocError)?;
+        if end > self.buf.len() {
+            return Err(AllocError);
+        }
+        self.used.set(end);
+        self.allocs.set(self.allocs.get() + 1);
+        let ptr = unsafe { NonNull::new_unchecked(base.add(start)) };
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {}
+}
+
+/// Runs `f`, returning its result and how many global allocations it made.
+fn global_allocs<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = GLOBAL_ALLOCS.get();
+    let r = f();
+    (r, GLOBAL_ALLOCS.get() - before)
+}
+
+#[test]
+fn escape_in_uses_only_the_arena() {
+    let input = "a\"b\\c\n中😊\u{1}".repeat(100);
+    let expected = escape(&input);
+    let arena = Arena::new();
+    let (escaped, allocs) = global_allocs(|| escape_in(&input, &arena));
+    assert_eq!(allocs, 0);
+    assert_eq!(arena.allocs.get(), 1);
+    assert_eq!(escaped[..], *expected.as_bytes());
+}
+
+#[test]
+fn escape_to_arena_vec_uses_only_the_arena() {
+    let inputs = ["", "hello", "\"quoted\"\n", &"中\\".repeat(500)];
+    let arena = Arena::new();
+    let (escaped, allocs) = global_allocs(|| {
+        let mut dst = AllocVec::new_in(&arena);
+        for input in inputs {
+            escape_to(input, &mut dst);
+        }
+        dst
+    });
+    assert_eq!(allocs, 0);
+    assert!(arena.allocs.get() > 0);
+    let expected: String = inputs.iter().map(|input| escape(input)).collect();
+    assert_eq!(escaped[..], *expected.as_bytes());
+}
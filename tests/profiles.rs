@@ -0,0 +1,132 @@
+//! Golden outputs of each escape profile.
+//!
+//! The expected strings are what the encoders print for the same input. The
+//! Python and JavaScript ones were captured from `json.dumps` (Python 3.11) and
+//! `JSON.stringify` (Node 20). The Go, PHP and Jackson ones were not captured:
+//! they are written from `encoding/json`'s `appendString`, `ext/json`'s
+//! `php_json_escape_string` and Jackson's `CharTypes` escape tables.
+
+use json_escape_simd::{Profile, escape, escape_into_with_profile, escape_with_profile};
+
+/// Slash, HTML chars, DEL, controls with and without short escapes, quote,
+/// backslash, 2-, 3- and 4-byte UTF-8, and the line separators.
+const INPUT: &str = "a/<>&\x7f\x1f\x0b\x08\x0c\n\"\\é中😊\u{2028}\u{2029}z";
+
+#[test]
+fn serde_json() {
+    let expected = "\"a/<>&\x7f\\u001f\\u000b\\b\\f\\n\\\"\\\\é中😊\u{2028}\u{2029}z\"";
+    assert_eq!(escape_with_profile(INPUT, Profile::SerdeJson), expected);
+    assert_eq!(serde_json::to_string(INPUT).unwrap(), expected);
+}
+
+#[test]
+fn ecma262() {
+    let expected = "\"a/<>&\x7f\\u001f\\u000b\\b\\f\\n\\\"\\\\é中😊\u{2028}\u{2029}z\"";
+    assert_eq!(escape_with_profile(INPUT, Profile::Ecma262), expected);
+}
+
+#[test]
+fn go_encoding_json() {
+    let expected =
+        "\"a/\\u003c\\u003e\\u0026\x7f\\u001f\\u000b\\b\\f\\n\\\"\\\\é中😊\\u2028\\u2029z\"";
+    assert_eq!(
+        escape_with_profile(INPUT, Profile::GoEncodingJson),
+        expected
+    );
+    // Other chars starting with the separators' lead byte pass through.
+    assert_eq!(
+        escape_with_profile("\u{2027}\u{2028}\u{202a}", Profile::GoEncodingJson),
+        "\"\u{2027}\\u2028\u{202a}\"",
+    );
+}
+
+#[test]
+fn python_json() {
+    let expected = r#""a/<>&\u007f\u001f\u000b\b\f\n\"\\\u00e9\u4e2d\ud83d\ude0a\u2028\u2029z""#;
+    assert_eq!(escape_with_profile(INPUT, Profile::PythonJson), expected);
+}
+
+#[test]
+fn php_json_encode() {
+    let expected =
+        "\"a\\/<>&\x7f\\u001f\\u000b\\b\\f\\n\\\"\\\\\\u00e9\\u4e2d\\ud83d\\ude0a\\u2028\\u2029z\"";
+    assert_eq!(escape_with_profile(INPUT, Profile::PhpJsonEncode), expected);
+}
+
+#[test]
+fn jackson() {
+    let expected = "\"a/<>&\x7f\\u001F\\u000B\\b\\f\\n\\\"\\\\é中😊\u{2028}\u{2029}z\"";
+    assert_eq!(escape_with_profile(INPUT, Profile::Jackson), expected);
+}
+
+const PROFILES: [Profile; 6] = [
+    Profile::SerdeJson,
+    Profile::Ecma262,
+    Profile::GoEncodingJson,
+    Profile::PythonJson,
+    Profile::PhpJsonEncode,
+    Profile::Jackson,
+];
+
+#[test]
+fn decodes_back() {
+    let inputs = ["", "plain", "中", "😊", "/", INPUT, &INPUT.repeat(50)];
+    for input in inputs {
+        for profile in PROFILES {
+            let escaped = escape_with_profile(input, profile);
+            let decoded: String = serde_json::from_str(&escaped).unwrap();
+            assert_eq!(decoded, input, "{profile:?}");
+            if matches!(profile, Profile::PythonJson | Profile::PhpJsonEncode) {
+                assert!(escaped.is_ascii(), "{profile:?}");
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "too slow under Miri")]
+fn long_runs_between_specials() {
+    // Runs longer than a kernel window on both sides of the specials.
+    let clean = "\"x\\".repeat(30_000);
+    let input = format!("{clean}<中/\u{1}{clean}");
+    let escaped = escape(&clean);
+    let clean = &escaped[1..escaped.len() - 1];
+    let cases = [
+        (Profile::GoEncodingJson, "\\u003c中/\\u0001"),
+        (Profile::PythonJson, "<\\u4e2d/\\u0001"),
+        (Profile::PhpJsonEncode, "<\\u4e2d\\/\\u0001"),
+        (Profile::Jackson, "<中/\\u0001"),
+    ];
+    for (profile, middle) in cases {
+        let mut dst = b"[".to_vec();
+        escape_into_with_profile(&input, profile, &mut dst);
+        let expected = format!("[\"{clean}{middle}{clean}\"");
+        assert!(dst == expected.as_bytes(), "{profile:?}");
+    }
+}
+
+#[test]
+fn special_at_every_offset() {
+    // Before, inside and after the 16-byte blocks the specials are found in.
+    let cases = [
+        (Profile::GoEncodingJson, "<", "\\u003c"),
+        (Profile::GoEncodingJson, "\u{2029}", "\\u2029"),
+        (Profile::PythonJson, "\x7f", "\\u007f"),
+        (Profile::PythonJson, "é", "\\u00e9"),
+        (Profile::PhpJsonEncode, "/", "\\/"),
+        (Profile::PhpJsonEncode, "😊", "\\ud83d\\ude0a"),
+        (Profile::Jackson, "\u{1e}", "\\u001E"),
+    ];
+    for (profile, special, escaped) in cases {
+        for before in 0..48 {
+            let pad = "x".repeat(before);
+            let input = format!("{pad}{special}{pad}{special}");
+            let expected = format!("\"{pad}{escaped}{pad}{escaped}\"");
+            assert_eq!(
+                escape_with_profile(&input, profile),
+                expected,
+                "{profile:?}"
+            );
+        }
+    }
+}
@@ -70,3 +70,84 @@ fn stress_multibyte_utf8_boundaries() {
         check(&s);
     }
 }
+
+#[test]
+fn stress_every_ascii_byte_at_every_lane() {
+    // Catches per-lane classification mistakes, such as a carry or borrow in a
+    // word-at-a-time fallback leaking into a neighbouring byte: every byte value
+    // sits at every offset of a 64-byte chunk, between both clean and escaped
+    // neighbours.
+    for b in 0u8..0x80 {
+        for neighbour in ['a', '\u{0}', '\u{7f}', 'é'] {
+            for pos in 0..64usize {
+                let mut s: String = std::iter::repeat_n(neighbour, pos).collect();
+                s.push(b as char);
+                s.extend(std::iter::repeat_n(neighbour, 64 - pos));
+                check(&s);
+            }
+        }
+    }
+}
+
+#[test]
+fn stress_dense_mixed_escapes() {
+    // Blocks full of two-byte escapes, interrupted by `\u00XX` ones, at every
+    // alignment relative to the 16-byte blocks of the dense expansion path.
+    let patterns = [
+        "\"\\",
+        "a\"b\\c\n",
+        "\t\r\u{8}\u{c}\"\\\n",
+        "{\"k\":\"v\"},",
+        "\"\u{1}\\\u{1f}",
+    ];
+    for pattern in patterns {
+        for prefix in 0..16usize {
+            let mut s = "a".repeat(prefix);
+            for _ in 0..40 {
+                s.push_str(pattern);
+                check(&s);
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "too slow under Miri")]
+fn escape_into_window_boundaries() {
+    use json_escape_simd::escape_into;
+    // `escape_into` escapes 64 KiB of input at a time. Put clean bytes,
+    // escapes and multi-byte chars across those boundaries, appending to a
+    // non-empty destination that has no slack.
+    const WINDOW: usize = 64 * 1024;
+    for len in [WINDOW - 1, WINDOW, WINDOW + 1, 3 * WINDOW + 7] {
+        for tail in ["", "\"", "\u{0}", "中", "𝄞\\"] {
+            for fill in ['a', '"', '\u{1f}'] {
+                let mut input: String = std::iter::repeat_n(fill, len - 2).collect();
+                input.push_str(tail);
+                input.push_str("é\nb");
+                let expected = serde_json::to_string(&input).unwrap();
+                let mut dst = Vec::with_capacity(expected.len() + 1);
+                dst.push(b'[');
+                escape_into(&input, &mut dst);
+                assert_eq!(
+                    dst[1..],
+                    *expected.as_bytes(),
+                    "len {len} tail {tail:?} fill {fill:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "too slow under Miri")]
+fn escape_into_reserves_for_one_window() {
+    use json_escape_simd::escape_into;
+    // Mostly clean input should not make `escape_into` reserve 6x its size: at
+    // most the output plus one window's worst case, doubled by `Vec`'s growth.
+    let input = "a".repeat(8 << 20);
+    let mut dst = Vec::new();
+    escape_into(&input, &mut dst);
+    assert_eq!(dst.len(), input.len() + 2);
+    assert!(dst.capacity() <= 2 * (dst.len() + 64 * 1024 * 6 + 35));
+}
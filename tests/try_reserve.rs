@@ -0,0 +1,84 @@
+//! Allocation failures in `try_escape` and `try_escape_into`.
+//!
+//! A global allocator that refuses to grow past a per-thread limit stands in
+//! for a memory-capped process, so the error paths run without exhausting the
+//! machine.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use json_escape_simd::{escape, try_escape, try_escape_into};
+
+struct FailingAlloc;
+
+thread_local! {
+    // Allocations (and reallocations) above this many bytes fail.
+    static LIMIT: Cell<usize> = const { Cell::new(usize::MAX) };
+}
+
+fn over_limit(size: usize) -> bool {
+    LIMIT.try_with(|limit| size > limit.get()).unwrap_or(false)
+}
+
+unsafe impl GlobalAlloc for FailingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if over_limit(layout.size()) {
+            return std::ptr::null_mut();
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if over_limit(new_size) {
+            return std::ptr::null_mut();
+        }
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: FailingAlloc = FailingAlloc;
+
+/// Runs `f` with allocations above `limit` bytes failing on this thread.
+fn with_limit<R>(limit: usize, f: impl FnOnce() -> R) -> R {
+    LIMIT.set(limit);
+    let r = f();
+    LIMIT.set(usize::MAX);
+    r
+}
+
+#[test]
+fn try_escape_reports_allocation_failure() {
+    let input = "a\"b".repeat(10_000);
+    let scratch = input.len() * 6 + 35;
+    assert!(with_limit(scratch - 1, || try_escape(&input)).is_err());
+    let escaped = with_limit(scratch, || try_escape(&input)).unwrap();
+    assert_eq!(escaped, escape(&input));
+}
+
+#[test]
+fn try_escape_into_reports_allocation_failure() {
+    let input = "a\"b".repeat(10_000);
+    let mut dst = b"[".to_vec();
+    assert!(with_limit(1024, || try_escape_into(&input, &mut dst)).is_err());
+    assert_eq!(dst, b"[");
+    try_escape_into(&input, &mut dst).unwrap();
+    assert_eq!(dst[1..], *escape(&input).as_bytes());
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "too slow under Miri")]
+fn try_escape_into_keeps_contents_when_failing_midway() {
+    // The first 64 KiB window fits, growing for a later one does not.
+    let input = "a".repeat(4 << 16);
+    let first_window = (1 << 16) * 6 + 35;
+    let mut dst = b"[".to_vec();
+    let limit = first_window + 1024;
+    assert!(with_limit(limit, || try_escape_into(&input, &mut dst)).is_err());
+    assert_eq!(dst, b"[");
+    assert!(dst.capacity() > first_window);
+}